@@ -1,27 +1,14 @@
 use std::io::{stdin, stdout, Write};
 
-use nso::splatoon3::{self, graphql_query, keys};
-use nso::{
-    get_access_token,
-    get_f1,
-    get_f2,
-    get_login_token,
-    get_login_url_and_verifier,
-    get_session_token,
-    get_session_token_code_from_select_url,
-    get_user_info,
-    UrlAndVerifier,
-};
-use reqwest::Client;
+use nso::splatoon3::graphql_query;
+use nso::{Credentials, LoginRequest, NsoClient, NsoSession, Splatoon3Tokens};
 
-#[tokio::main]
-#[allow(clippy::too_many_lines)]
-async fn main() {
-    let UrlAndVerifier {
-        url,
-        verifier,
-    } = get_login_url_and_verifier();
-    println!("Login URL: {url}");
+/// Where tokens are saved between runs, so the login only happens once
+const CREDENTIALS_PATH: &str = "credentials.json";
+
+async fn log_in(client: &NsoClient) -> NsoSession {
+    let login_request = LoginRequest::new(client);
+    println!("Login URL: {}", login_request.url);
     println!("Enter the 'Select this person' URL here:");
     print!(">>> ");
     stdout().flush().expect("Unexpected IO error");
@@ -30,39 +17,42 @@ async fn main() {
         .next()
         .expect("Unexpected EOF")
         .expect("Unexpected IO error");
-    let session_token_code = get_session_token_code_from_select_url(select_url.trim())
-        .expect("Invalid 'Select this person' URL");
-    println!("session_token_code: {session_token_code}");
-    let client = Client::new();
-    let session_token = get_session_token(session_token_code, &verifier, &client)
+    let session_token = login_request
+        .redeem(&select_url, client)
         .await
         .expect("Failed to get session_token");
-    println!("session_token: {session_token}");
-    let tokens = get_access_token(&session_token, &client)
-        .await
-        .expect("Failed to get tokens");
-    println!("access_token: {}", tokens.access_token);
-    println!("id_token: {}", tokens.id_token);
-    let user_info = get_user_info(&tokens.access_token, &client)
-        .await
-        .expect("Failed to get user_info");
-    let nso_f = get_f1(&tokens.id_token, &client)
-        .await
-        .expect("Failed to get nso_f");
-    let login_token = get_login_token(&nso_f, &tokens.id_token, &user_info, &client)
-        .await
-        .expect("Failed to get login_token");
-    let app_f = get_f2(&login_token, &client)
-        .await
-        .expect("Failed to get app_f");
-    let web_token = splatoon3::get_web_token(&app_f, &login_token, &client)
-        .await
-        .expect("Failed to get web_token");
-    let bullet_token = splatoon3::get_bullet_token(&web_token, &client)
-        .await
-        .expect("Failed to get bullet_token");
-    println!("web_token: {web_token}");
-    println!("bullet_token: {bullet_token}");
+    println!("session_token: {}", session_token.expose());
+    NsoSession::new(session_token, client.clone())
+}
+
+#[tokio::main]
+#[allow(clippy::too_many_lines)]
+async fn main() {
+    let client = NsoClient::default();
+    let session = match Credentials::load(CREDENTIALS_PATH) {
+        Ok(credentials) => NsoSession::from_credentials(&credentials, client.clone())
+            .expect("Saved credentials are incomplete"),
+        Err(_) => log_in(&client).await,
+    };
+    let tokens = session.tokens().await.expect("Failed to get tokens");
+    println!("access_token: {}", tokens.access_token.expose());
+    println!("id_token: {}", tokens.id_token.expose());
+    let user_info = session.user_info().await.expect("Failed to get user_info");
+    println!("Logged in as {} ({})", user_info.nickname, user_info.id);
+    let Splatoon3Tokens {
+        web_token,
+        bullet_token,
+    } = session
+        .splatoon3_tokens()
+        .await
+        .expect("Failed to get Splatoon 3 tokens");
+    println!("web_token: {}", web_token.expose());
+    println!("bullet_token: {}", bullet_token.expose());
+    session
+        .credentials()
+        .await
+        .save(CREDENTIALS_PATH)
+        .expect("Failed to save credentials");
     println!("---");
     println!(
         "Schedules: {}",
@@ -70,7 +60,7 @@ async fn main() {
             &bullet_token,
             &user_info.language,
             &web_token,
-            keys::SCHEDULES,
+            "StageScheduleQuery",
             &client,
         )
         .await
@@ -86,7 +76,7 @@ async fn main() {
             &bullet_token,
             &user_info.language,
             &web_token,
-            keys::SPLATNET,
+            "GesotownQuery",
             &client,
         )
         .await
@@ -102,7 +92,7 @@ async fn main() {
             &bullet_token,
             &user_info.language,
             &web_token,
-            keys::SALMON,
+            "CoopHistoryQuery",
             &client,
         )
         .await
@@ -118,7 +108,7 @@ async fn main() {
             &bullet_token,
             &user_info.language,
             &web_token,
-            keys::SPLATFEST_OVERVIEW,
+            "FestRecordQuery",
             &client,
         )
         .await
@@ -134,7 +124,7 @@ async fn main() {
             &bullet_token,
             &user_info.language,
             &web_token,
-            keys::LATEST_BATTLES,
+            "LatestBattleHistoriesQuery",
             &client,
         )
         .await
@@ -150,7 +140,7 @@ async fn main() {
             &bullet_token,
             &user_info.language,
             &web_token,
-            keys::GEAR,
+            "MyOutfitCommonDataEquipmentsQuery",
             &client,
         )
         .await
@@ -0,0 +1,54 @@
+//! The login chain from `main.rs`, run against the mock server
+//!
+//! Run with `cargo run --example mock --features mock`. The behaviour of each
+//! step is checked by the integration tests, run with
+//! `cargo test --features mock`.
+use nso::mock::MockServer;
+use nso::splatoon3::graphql_query;
+use nso::{LoginRequest, NsoSession, Splatoon3Tokens};
+
+#[tokio::main]
+async fn main() {
+    let server = MockServer::start()
+        .await
+        .expect("Failed to start mock server");
+    let client = server.client();
+    let login_request = LoginRequest::new(&client);
+    println!("Log in at {}", login_request.url);
+    // The mock server accepts any session_token_code
+    let select_url = format!(
+        concat!(
+            "npf71b963c1b7b6d119://auth#session_state=mock",
+            "&session_token_code=mock-code&state={}",
+        ),
+        login_request.state,
+    );
+    let session_token = login_request
+        .redeem(&select_url, &client)
+        .await
+        .expect("Failed to get session_token");
+
+    let session = NsoSession::new(session_token, client.clone());
+    let user_info = session.user_info().await.expect("Failed to get user_info");
+    let Splatoon3Tokens {
+        web_token,
+        bullet_token,
+    } = session
+        .splatoon3_tokens()
+        .await
+        .expect("Failed to get Splatoon 3 tokens");
+    let schedules = graphql_query(
+        &bullet_token,
+        &user_info.language,
+        &web_token,
+        "StageScheduleQuery",
+        &client,
+    )
+    .await
+    .expect("GraphQL query failed unexpectedly")
+    .text()
+    .await
+    .expect("GraphQL query failed");
+    println!("Schedules: {schedules}");
+    println!("Mock login chain succeeded");
+}
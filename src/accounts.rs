@@ -0,0 +1,286 @@
+//! Managing several Nintendo Accounts at once
+//!
+//! Each account gets its own [`NsoSession`] and its own HTTP client, so
+//! cookies (such as Splatoon 2's `iksm_session`) never leak between accounts.
+//! Otherwise, every account's client is set up like the registry's (see
+//! [`AccountRegistry::with_client`]), and its HTTP client is built by the
+//! registry's [`with_http_builder`](AccountRegistry::with_http_builder)
+//! factory.
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use reqwest::{Client, ClientBuilder};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use zeroize::Zeroizing;
+
+use crate::credentials::write_private;
+use crate::f_token::Imink;
+use crate::{
+    Credentials,
+    Endpoints,
+    FTokenProvider,
+    NsoClient,
+    NsoError,
+    NsoSession,
+    SessionToken,
+};
+
+/// The version of the registry format written by this version of the crate
+pub const REGISTRY_VERSION: u32 = 1;
+
+/// A Nintendo Account, and the tokens obtained for it
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Account {
+    /// The Nintendo Account ID
+    pub id: String,
+    /// The account's nickname, when it was last seen
+    pub nickname: String,
+    pub credentials: Credentials,
+}
+
+#[derive(Serialize)]
+struct RegistryFile {
+    version: u32,
+    accounts: Vec<Account>,
+}
+
+/// A registry file as read, before each account's credentials are migrated
+#[derive(Deserialize)]
+struct StoredRegistryFile {
+    version: u32,
+    accounts: Vec<StoredAccount>,
+}
+
+#[derive(Deserialize)]
+struct StoredAccount {
+    id: String,
+    nickname: String,
+    credentials: Value,
+}
+
+type HttpBuilder = dyn Fn() -> ClientBuilder + Send + Sync;
+
+/// Every Nintendo Account being used, keyed by Nintendo Account ID
+///
+/// Accounts can be looked up by ID or by nickname.
+pub struct AccountRegistry {
+    accounts: BTreeMap<String, Account>,
+    sessions: HashMap<String, Arc<NsoSession>>,
+    client: NsoClient,
+    http_builder: Arc<HttpBuilder>,
+    f_token_provider: Arc<dyn FTokenProvider>,
+}
+
+impl AccountRegistry {
+    /// Create an empty registry, whose sessions get their f-tokens from
+    /// [`Imink`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            accounts: BTreeMap::new(),
+            sessions: HashMap::new(),
+            client: NsoClient::default(),
+            http_builder: Arc::new(Client::builder),
+            f_token_provider: Arc::new(Imink),
+        }
+    }
+
+    /// Base every account's client on `client`, keeping its endpoints and
+    /// other settings (such as a [`Recorder`](crate::cassette::Recorder))
+    ///
+    /// Each account still gets its own cookie store, so `client`'s HTTP
+    /// client isn't used; see [`with_http_builder`](Self::with_http_builder).
+    #[must_use]
+    pub fn with_client(mut self, client: NsoClient) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Build every account's HTTP client from a fresh `ClientBuilder` made by
+    /// `builder` (for example, to set a proxy, timeouts or a user agent)
+    ///
+    /// The cookie store is enabled on each builder, so that every account
+    /// gets its own.
+    #[must_use]
+    pub fn with_http_builder(
+        mut self,
+        builder: impl Fn() -> ClientBuilder + Send + Sync + 'static,
+    ) -> Self {
+        self.http_builder = Arc::new(builder);
+        self
+    }
+
+    /// Send every account's requests to `endpoints` instead
+    #[must_use]
+    pub fn with_endpoints(mut self, endpoints: Endpoints) -> Self {
+        self.client = self.client.with_endpoints(endpoints);
+        self
+    }
+
+    /// Get every account's f-tokens from `provider` instead
+    #[must_use]
+    pub fn with_f_token_provider(
+        mut self,
+        provider: impl FTokenProvider + 'static,
+    ) -> Self {
+        self.f_token_provider = Arc::new(provider);
+        self
+    }
+
+    /// Load the accounts saved at `path`
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be read or parsed, or it (or any account's
+    /// credentials) was written by a newer version of the crate.
+    pub fn load(mut self, path: impl AsRef<Path>) -> Result<Self, NsoError> {
+        let file: StoredRegistryFile = serde_json::from_str(&fs::read_to_string(path)?)
+            .map_err(|err| NsoError::InvalidRegistry(err.to_string()))?;
+        if file.version > REGISTRY_VERSION {
+            return Err(NsoError::UnsupportedRegistryVersion(file.version));
+        }
+        let accounts = file
+            .accounts
+            .into_iter()
+            .map(|account| {
+                Ok(Account {
+                    id: account.id,
+                    nickname: account.nickname,
+                    credentials: Credentials::from_value(account.credentials)?,
+                })
+            })
+            .collect::<Result<Vec<_>, NsoError>>()?;
+        for account in accounts {
+            self.sessions.remove(&account.id);
+            self.accounts.insert(account.id.clone(), account);
+        }
+        Ok(self)
+    }
+
+    /// Save every account, with the latest tokens from its session, to
+    /// `path`
+    ///
+    /// The file is replaced atomically, so a crash mid-write can't corrupt it.
+    /// On Unix, it is only readable by the user.
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be written.
+    pub async fn save(&mut self, path: impl AsRef<Path>) -> Result<(), NsoError> {
+        self.sync().await;
+        let file = RegistryFile {
+            version: REGISTRY_VERSION,
+            accounts: self.accounts.values().cloned().collect(),
+        };
+        let contents = Zeroizing::new(
+            serde_json::to_string_pretty(&file)
+                .map_err(|err| NsoError::InvalidRegistry(err.to_string()))?,
+        );
+        write_private(path.as_ref(), contents.as_bytes())?;
+        Ok(())
+    }
+
+    /// Copy the latest tokens from each account's session into its
+    /// [`credentials`](Account::credentials)
+    pub async fn sync(&mut self) {
+        for (id, session) in &self.sessions {
+            if let Some(account) = self.accounts.get_mut(id) {
+                account.credentials = session.credentials().await;
+            }
+        }
+    }
+
+    /// Log in with `session_token` and add (or update) the account it
+    /// belongs to
+    ///
+    /// # Errors
+    ///
+    /// If the account information cannot be requested.
+    pub async fn add(
+        &mut self,
+        session_token: impl Into<SessionToken>,
+    ) -> Result<&Account, NsoError> {
+        let session = NsoSession::new(session_token, self.account_client()?)
+            .with_f_token_provider(Arc::clone(&self.f_token_provider));
+        let user_info = session.user_info().await?;
+        let account = Account {
+            id: user_info.id.clone(),
+            nickname: user_info.nickname,
+            credentials: session.credentials().await,
+        };
+        self.sessions.insert(account.id.clone(), Arc::new(session));
+        self.accounts.insert(account.id.clone(), account);
+        Ok(&self.accounts[&user_info.id])
+    }
+
+    /// Forget an account, given its ID
+    pub fn remove(&mut self, id: &str) -> Option<Account> {
+        self.sessions.remove(id);
+        self.accounts.remove(id)
+    }
+
+    /// Every account, in order of ID
+    pub fn accounts(&self) -> impl Iterator<Item = &Account> {
+        self.accounts.values()
+    }
+
+    /// Find an account by its ID or its nickname
+    ///
+    /// # Errors
+    ///
+    /// [`NsoError::UnknownAccount`] if no account matches, or
+    /// [`NsoError::AmbiguousAccount`] if `query` is the nickname of more than
+    /// one account.
+    pub fn find(&self, query: &str) -> Result<&Account, NsoError> {
+        if let Some(account) = self.accounts.get(query) {
+            return Ok(account);
+        }
+        let mut matches = self
+            .accounts
+            .values()
+            .filter(|account| account.nickname == query);
+        match (matches.next(), matches.next()) {
+            (Some(account), None) => Ok(account),
+            (Some(_), Some(_)) => Err(NsoError::AmbiguousAccount(query.to_string())),
+            (None, _) => Err(NsoError::UnknownAccount(query.to_string())),
+        }
+    }
+
+    /// Get the session for the account with the ID or nickname `query`,
+    /// creating it from the account's credentials if needed
+    ///
+    /// # Errors
+    ///
+    /// If no account (or more than one) matches, or the account's
+    /// credentials don't include a `session_token`.
+    pub fn session(&mut self, query: &str) -> Result<Arc<NsoSession>, NsoError> {
+        let account = self.find(query)?;
+        if let Some(session) = self.sessions.get(&account.id) {
+            return Ok(Arc::clone(session));
+        }
+        let client = self.account_client()?;
+        let session = Arc::new(
+            NsoSession::from_credentials(&account.credentials, client)?
+                .with_f_token_provider(Arc::clone(&self.f_token_provider)),
+        );
+        self.sessions
+            .insert(account.id.clone(), Arc::clone(&session));
+        Ok(session)
+    }
+
+    /// The registry's client with its own cookie store, so that no two
+    /// accounts share cookies
+    fn account_client(&self) -> Result<NsoClient, NsoError> {
+        let http = (self.http_builder)().cookie_store(true).build()?;
+        Ok(self.client.clone().with_http(http))
+    }
+}
+
+impl Default for AccountRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
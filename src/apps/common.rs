@@ -1,27 +1,105 @@
 use std::collections::HashMap;
 
-use reqwest::header::{ACCEPT, ACCEPT_ENCODING, CONNECTION, HOST, USER_AGENT};
-use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-use crate::{AppFToken, NSO_USER_AGENT, NSO_VERSION};
+use crate::{coral, AppFToken, CoralToken, GameWebToken, NsoClient, NsoError};
+
+/// A token granting access to a game's web service
+#[derive(Clone, Debug)]
+pub struct WebServiceToken {
+    pub access_token: GameWebToken,
+    /// The number of seconds the token is valid for
+    pub expires_in: u64,
+}
+
+/// A web service available from the Nintendo Switch Online app, as returned by
+/// [`get_web_services`]
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebService {
+    /// The ID to request the service's web token with (see
+    /// [`get_web_service_token`])
+    pub id: u64,
+    pub name: String,
+    /// The URL the app opens the service at
+    pub uri: String,
+    pub image_uri: String,
+    /// The domains the service's web view may navigate to
+    #[serde(rename = "whiteList")]
+    pub allowed_domains: Vec<String>,
+    pub custom_attributes: Vec<CustomAttribute>,
+}
+
+impl WebService {
+    /// The value of the custom attribute `key`, if set
+    #[must_use]
+    pub fn custom_attribute(&self, key: &str) -> Option<&str> {
+        self.custom_attributes
+            .iter()
+            .find(|attribute| attribute.attr_key == key)
+            .map(|attribute| attribute.attr_value.as_str())
+    }
+}
+
+/// A key-value setting for a [`WebService`], such as `verifyMembership`
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CustomAttribute {
+    pub attr_key: String,
+    pub attr_value: String,
+}
+
+/// List the web services available to the user
+///
+/// # Errors
+///
+/// If the request to Nintendo fails, or if Coral responds with an error (for
+/// example, if the login token has expired)
+pub async fn get_web_services(
+    login_token: &CoralToken,
+    client: &NsoClient,
+) -> Result<Vec<WebService>, NsoError> {
+    #[derive(Serialize)]
+    struct Parameter {}
+    coral::call(
+        "/v1/Game/ListWebServices",
+        Some(login_token.expose()),
+        Parameter {},
+        client,
+    )
+    .await
+}
 
 /// Get the access token for a game, based on the user's F token and login token
 ///
+/// See [`get_web_service_token`] for web services chosen at runtime.
+///
 /// # Errors
 ///
-/// If the request to Nintendo fails (for example, if a token provided is
-/// invalid)
+/// If the request to Nintendo fails, or if Coral responds with an error (for
+/// example, if a token provided is invalid)
 pub async fn get_game_web_token<const GAME_ID: u64>(
     f: &AppFToken,
-    login_token: &str,
-    client: &Client,
-) -> Result<String, reqwest::Error> {
-    #[derive(Serialize)]
-    struct Body<'a> {
-        parameter: Parameter<'a>,
-    }
+    login_token: &CoralToken,
+    client: &NsoClient,
+) -> Result<WebServiceToken, NsoError> {
+    get_web_service_token(GAME_ID, f, login_token, client).await
+}
+
+/// Get the access token for the web service `service_id` (see
+/// [`WebService::id`]), based on the user's F token and login token
+///
+/// # Errors
+///
+/// If the request to Nintendo fails, or if Coral responds with an error (for
+/// example, if a token provided is invalid, or the service doesn't exist)
+pub async fn get_web_service_token(
+    service_id: u64,
+    f: &AppFToken,
+    login_token: &CoralToken,
+    client: &NsoClient,
+) -> Result<WebServiceToken, NsoError> {
     #[derive(Serialize)]
     #[allow(non_snake_case)]
     struct Parameter<'a> {
@@ -32,41 +110,28 @@ pub async fn get_game_web_token<const GAME_ID: u64>(
         requestId: &'a str,
     }
     #[derive(Deserialize)]
-    struct Resp {
-        result: Result,
-        #[serde(flatten)]
-        _rest: HashMap<String, Value>,
-    }
-    #[derive(Deserialize)]
     #[allow(non_snake_case)]
     struct Result {
-        accessToken: String,
+        accessToken: GameWebToken,
+        expiresIn: u64,
         #[serde(flatten)]
         _rest: HashMap<String, Value>,
     }
-    Ok(client
-        .post("https://api-lp1.znc.srv.nintendo.net/v2/Game/GetWebServiceToken")
-        .header(HOST, "api-lp1.znc.srv.nintendo.net")
-        .header(USER_AGENT, NSO_USER_AGENT)
-        .header(ACCEPT, "application/json")
-        .header("X-ProductVersion", NSO_VERSION)
-        .header(CONNECTION, "Keep-Alive")
-        .bearer_auth(login_token)
-        .header("X-Platform", "Android")
-        .header(ACCEPT_ENCODING, "gzip")
-        .json(&Body {
-            parameter: Parameter {
-                id: GAME_ID,
-                f: &f.f,
-                registrationToken: login_token,
-                timestamp: f.timestamp,
-                requestId: &f.request_id,
-            },
-        })
-        .send()
-        .await?
-        .json::<Resp>()
-        .await?
-        .result
-        .accessToken)
+    let result = coral::call::<_, Result>(
+        "/v2/Game/GetWebServiceToken",
+        Some(login_token.expose()),
+        Parameter {
+            id: service_id,
+            f: &f.f,
+            registrationToken: login_token.expose(),
+            timestamp: f.timestamp,
+            requestId: &f.request_id,
+        },
+        client,
+    )
+    .await?;
+    Ok(WebServiceToken {
+        access_token: result.accessToken,
+        expires_in: result.expiresIn,
+    })
 }
@@ -1,4 +1,11 @@
 mod common;
 pub mod splatoon2;
 pub mod splatoon3;
-pub use common::get_game_web_token;
+pub use common::{
+    get_game_web_token,
+    get_web_service_token,
+    get_web_services,
+    CustomAttribute,
+    WebService,
+    WebServiceToken,
+};
@@ -4,12 +4,21 @@ use reqwest::header::{
     ACCEPT_LANGUAGE,
     CONNECTION,
     DNT,
-    HOST,
     USER_AGENT,
 };
-use reqwest::Client;
 
-use crate::{get_game_web_token, AppFToken, WEB_VIEW_USER_AGENT};
+use crate::transport::{check_status, send};
+use crate::{
+    get_game_web_token,
+    AppFToken,
+    CoralToken,
+    GameWebToken,
+    IksmSession,
+    NsoClient,
+    NsoError,
+    WebServiceToken,
+    WEB_VIEW_USER_AGENT,
+};
 /// Splatoon 2's internal ID
 pub const GAME_ID: u64 = 5_741_031_244_955_648;
 
@@ -17,13 +26,13 @@ pub const GAME_ID: u64 = 5_741_031_244_955_648;
 ///
 /// # Errors
 ///
-/// If the request to Nintendo fails (for example, if a token provided is
-/// invalid)
+/// If the request to Nintendo fails, or if Coral responds with an error (for
+/// example, if a token provided is invalid)
 pub async fn get_web_token(
     f: &AppFToken,
-    login_token: &str,
-    client: &Client,
-) -> Result<String, reqwest::Error> {
+    login_token: &CoralToken,
+    client: &NsoClient,
+) -> Result<WebServiceToken, NsoError> {
     get_game_web_token::<GAME_ID>(f, login_token, client).await
 }
 
@@ -32,15 +41,14 @@ pub async fn get_web_token(
 /// # Errors
 ///
 /// An `Err` will be returned if the request to Nintendo fails (for example, if
-/// the connection fails). If the server's response does not include the
-/// `iksm_session`, `Ok(None)` will be returned.
+/// the connection fails), or if the server's response does not include the
+/// `iksm_session` cookie.
 pub async fn get_iksm_session(
-    web_token: &str,
-    client: &Client,
-) -> Result<Option<String>, reqwest::Error> {
-    Ok(client
-        .get("https://app.splatoon2.nintendo.net/?lang=en-US")
-        .header(HOST, "app.splatoon2.nintendo.net")
+    web_token: &GameWebToken,
+    client: &NsoClient,
+) -> Result<IksmSession, NsoError> {
+    let request = client
+        .get(format!("{}/?lang=en-US", client.endpoints().splatoon2))
         .header("X-IsAppAnalyticsOptedIn", "false")
         .header(
             ACCEPT,
@@ -52,16 +60,18 @@ pub async fn get_iksm_session(
             ),
         )
         .header(ACCEPT_ENCODING, "gzip,deflate")
-        .header("X-GameWebToken", web_token)
+        .header("X-GameWebToken", web_token.expose())
         .header(ACCEPT_LANGUAGE, "en-US")
         .header("X-IsAnalyticsOptedIn", "false")
         .header(CONNECTION, "keep-alive")
         .header(DNT, "0")
         .header(USER_AGENT, WEB_VIEW_USER_AGENT)
-        .header("X-Requested-With", "com.nintendo.znca")
-        .send()
+        .header("X-Requested-With", "com.nintendo.znca");
+    let response = send("splatoon2.iksm_session", request, client).await?;
+    check_status(response)
         .await?
         .cookies()
         .find(|cookie| cookie.name() == "iksm_session")
-        .map(|cookie| cookie.value().to_string()))
+        .map(|cookie| IksmSession::new(cookie.value()))
+        .ok_or(NsoError::MissingCookie("iksm_session"))
 }
@@ -0,0 +1,287 @@
+use std::collections::HashMap;
+
+use reqwest::header::{ACCEPT_LANGUAGE, COOKIE, ORIGIN, REFERER, USER_AGENT};
+use reqwest::{Response, ResponseBuilderExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use self::queries::{is_hash, operation_name, resolve};
+use self::version::web_view_version;
+use crate::transport::{check_status, json, send};
+use crate::{
+    get_game_web_token,
+    AppFToken,
+    BulletToken,
+    CoralToken,
+    GameWebToken,
+    NsoClient,
+    NsoError,
+    WebServiceToken,
+    WEB_VIEW_USER_AGENT,
+};
+
+/// Read a saved copy of part of the web view from `tests/fixtures/splatoon3`
+#[cfg(test)]
+macro_rules! fixture {
+    ($name:literal) => {
+        include_str!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/splatoon3/",
+            $name,
+        ))
+    };
+}
+
+pub mod queries;
+pub mod version;
+pub(crate) mod web_view;
+
+/// Splatoon 3's internal ID
+pub const GAME_ID: u64 = 4_834_290_508_791_808;
+
+/// The hashes of some persisted queries, as of the crate's release
+///
+/// These are used when a query can't be found in the web view; see
+/// [`queries`].
+#[doc(hidden)]
+pub mod keys {
+    /// `StageScheduleQuery`
+    pub const SCHEDULES: &str = "7d4bb0565342b7385ceb97d109e14897";
+    /// `GesotownQuery`
+    pub const SPLATNET: &str = "a43dd44899a09013bcfd29b4b13314ff";
+    /// `CoopHistoryQuery`
+    pub const SALMON: &str = "817618ce39bcf5570f52a97d73301b30";
+    /// `SaleGearDetailOrderGesotownGearMutation`
+    pub const ORDER: &str = "b79b7a101a243912754f72437e2ad7e5";
+    /// `FestRecordQuery`
+    pub const SPLATFEST_OVERVIEW: &str = "44c76790b68ca0f3da87f2a3452de986";
+    /// `DetailFestRecordDetailQuery`
+    pub const SPLATFEST: &str = "2d661988c055d843b3be290f04fb0db9";
+    /// `LatestBattleHistoriesQuery`,
+    pub const LATEST_BATTLES: &str = "7d8b560e31617e981cf7c8aa1ca13a00";
+    /// `MyOutfitCommonDataEquipmentsQuery`
+    pub const GEAR: &str = "d29cd0c2b5e6bac90dd5b817914832f8";
+}
+
+/// Get the Splatoon 3 access token, based on the user's F token and login token
+///
+/// # Errors
+///
+/// If the request to Nintendo fails, or if Coral responds with an error (for
+/// example, if a token provided is invalid)
+pub async fn get_web_token(
+    f: &AppFToken,
+    login_token: &CoralToken,
+    client: &NsoClient,
+) -> Result<WebServiceToken, NsoError> {
+    get_game_web_token::<GAME_ID>(f, login_token, client).await
+}
+
+/// Get the `bullet_token`, based on the user's Splatoon 2 access token
+///
+/// # Errors
+///
+/// If the request to Nintendo fails (for example, if a token provided is
+/// invalid)
+pub async fn get_bullet_token(
+    web_token: &GameWebToken,
+    client: &NsoClient,
+) -> Result<BulletToken, NsoError> {
+    #[derive(Deserialize)]
+    #[allow(non_snake_case)]
+    struct Resp {
+        bulletToken: BulletToken,
+        #[serde(flatten)]
+        _rest: HashMap<String, Value>,
+    }
+    let base_url = &client.endpoints().splatoon3;
+    let request = client
+        .post(format!("{base_url}/api/bullet_tokens"))
+        .header(ORIGIN, base_url)
+        .header(REFERER, format!("{base_url}/"))
+        .header("X-Web-View-Ver", web_view_version(client).await)
+        .header(USER_AGENT, WEB_VIEW_USER_AGENT)
+        .header(COOKIE, format!("_dnt=0;_gtoken={}", web_token.expose()));
+    let response = send("splatoon3.bullet_token", request, client).await?;
+    Ok(json::<Resp>(response).await?.bulletToken)
+}
+
+/// Run the persisted query `query` with `variables`
+///
+/// `query` is either an operation name (such as `StageScheduleQuery`) or the
+/// hash of a persisted query; see [`queries::persisted_query_hash`].
+///
+/// # Errors
+///
+/// If `query` is an unknown operation name, SplatNet 3 rejects its hash as
+/// stale ([`NsoError::StaleQuery`]), or the request to Nintendo fails (for
+/// example, if a token provided is invalid).
+pub async fn graphql_query_with_variables<T: Serialize>(
+    bullet_token: &BulletToken,
+    lang: &str,
+    web_token: &GameWebToken,
+    query: &str,
+    variables: T,
+    client: &NsoClient,
+) -> Result<Response, NsoError> {
+    let request = GraphQlQuery {
+        bullet_token,
+        lang,
+        web_token,
+        variables: &variables,
+    };
+    let (mut query_hash, discovery) = resolve(query, client).await?;
+    if let Some(response) = request.send(&query_hash, client).await? {
+        return Ok(response);
+    }
+    let operation = if is_hash(query) {
+        operation_name(query, client).await
+    } else {
+        Some(query.to_string())
+    };
+    let web_view = client.splatoon3_web_view();
+    if let (true, Some(operation)) = (web_view.refetch_stale_queries, &operation) {
+        web_view.rediscover(discovery.as_ref(), client).await;
+        let (fresh_hash, _) = resolve(operation, client).await?;
+        if fresh_hash != query_hash {
+            if let Some(response) = request.send(&fresh_hash, client).await? {
+                return Ok(response);
+            }
+            query_hash = fresh_hash;
+        }
+    }
+    let operation = operation.unwrap_or_else(|| query_hash.clone());
+    #[cfg(feature = "tracing")]
+    tracing::warn!(
+        operation = %operation,
+        hash = %query_hash,
+        "SplatNet 3 rejected a stale persisted query",
+    );
+    Err(NsoError::StaleQuery {
+        operation,
+        hash: query_hash,
+    })
+}
+
+/// Everything about a GraphQL request except the query's hash
+struct GraphQlQuery<'a, T> {
+    bullet_token: &'a BulletToken,
+    lang: &'a str,
+    web_token: &'a GameWebToken,
+    variables: &'a T,
+}
+
+impl<T: Serialize> GraphQlQuery<'_, T> {
+    /// Send the query with `query_hash`, returning `None` if SplatNet 3
+    /// doesn't recognise the hash
+    async fn send(
+        &self,
+        query_hash: &str,
+        client: &NsoClient,
+    ) -> Result<Option<Response>, NsoError> {
+        #[derive(Serialize)]
+        struct Body<'a, T> {
+            extensions: Extensions<'a>,
+            variables: &'a T,
+        }
+        #[derive(Serialize)]
+        #[allow(non_snake_case)]
+        struct Extensions<'a> {
+            persistedQuery: PersistedQuery<'a>,
+        }
+        #[derive(Serialize)]
+        #[allow(non_snake_case)]
+        struct PersistedQuery<'a> {
+            sha256Hash: &'a str,
+            version: u32,
+        }
+        let base_url = &client.endpoints().splatoon3;
+        let request = client
+            .post(format!("{base_url}/api/graphql"))
+            .bearer_auth(self.bullet_token.expose())
+            .header("X-Web-View-Ver", web_view_version(client).await)
+            .header(ORIGIN, base_url)
+            .header(REFERER, format!("{base_url}/schedule/regular"))
+            .header(USER_AGENT, WEB_VIEW_USER_AGENT)
+            .header(ACCEPT_LANGUAGE, self.lang)
+            .header("X-Requested-With", "XMLHttpRequest")
+            .header(
+                COOKIE,
+                format!("_dnt=0;_gtoken={}", self.web_token.expose()),
+            )
+            .json(&Body {
+                extensions: Extensions {
+                    persistedQuery: PersistedQuery {
+                        sha256Hash: query_hash,
+                        version: 1,
+                    },
+                },
+                variables: self.variables,
+            });
+        let response = send("splatoon3.graphql", request, client).await?;
+        let response = check_status(response).await?;
+        // The body has to be read to check for errors, so the response is
+        // rebuilt around it afterwards
+        let status = response.status();
+        let headers = response.headers().clone();
+        let url = response.url().clone();
+        let body = response.bytes().await?;
+        if is_persisted_query_not_found(&body) {
+            return Ok(None);
+        }
+        let mut response = http::Response::builder().status(status).url(url);
+        if let Some(response_headers) = response.headers_mut() {
+            *response_headers = headers;
+        }
+        let response = response
+            .body(body)
+            .expect("response rebuilt from a valid response is valid");
+        Ok(Some(response.into()))
+    }
+}
+
+/// Whether a GraphQL response body is a `PersistedQueryNotFound` error
+fn is_persisted_query_not_found(body: &[u8]) -> bool {
+    #[derive(Deserialize)]
+    struct Errors {
+        errors: Vec<Error>,
+    }
+    #[derive(Deserialize)]
+    struct Error {
+        message: String,
+    }
+    serde_json::from_slice::<Errors>(body).is_ok_and(|body| {
+        body.errors
+            .iter()
+            .any(|error| error.message == "PersistedQueryNotFound")
+    })
+}
+
+/// Run the persisted query `query`, which takes no variables
+///
+/// See [`graphql_query_with_variables`].
+///
+/// # Errors
+///
+/// If `query` is an unknown operation name, SplatNet 3 rejects its hash as
+/// stale ([`NsoError::StaleQuery`]), or the request to Nintendo fails (for
+/// example, if a token provided is invalid).
+pub async fn graphql_query(
+    bullet_token: &BulletToken,
+    lang: &str,
+    web_token: &GameWebToken,
+    query: &str,
+    client: &NsoClient,
+) -> Result<Response, NsoError> {
+    #[derive(Serialize)]
+    struct NoVariables {}
+    graphql_query_with_variables(
+        bullet_token,
+        lang,
+        web_token,
+        query,
+        NoVariables {},
+        client,
+    )
+    .await
+}
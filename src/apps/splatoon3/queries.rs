@@ -0,0 +1,302 @@
+//! Looking up SplatNet 3's persisted queries by operation name
+//!
+//! SplatNet 3 only accepts GraphQL queries by the hash of a query baked into
+//! the web view, and the hashes change whenever Nintendo updates it. The web
+//! view's `main.*.js` lists every query and mutation with its hash, so the
+//! hashes are read from it the first time a client needs one, and cached on
+//! the client (and its clones). Queries missing from the web view (or all of
+//! them, if discovery fails) fall back to the hashes in [`keys`].
+//!
+//! Hashes can also be loaded from a JSON file mapping operation names to
+//! hashes (like the lists maintained by the community), with
+//! [`QueryRegistry::load`] and [`NsoClient::with_persisted_queries`].
+//!
+//! If SplatNet 3 rejects a query with `PersistedQueryNotFound`, its hash is
+//! stale, and [`NsoError::StaleQuery`] names the operation. Clients built with
+//! [`NsoClient::with_stale_query_refetch`] download the web view again and
+//! retry the query once with the new hash first.
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+
+use regex::Regex;
+
+use super::keys;
+use super::web_view::{fetch_main_script, Discovery, WebView};
+use crate::{NsoClient, NsoError};
+
+/// A map from GraphQL operation names (such as `StageScheduleQuery`) to the
+/// hashes of their persisted queries
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct QueryRegistry {
+    queries: BTreeMap<String, String>,
+}
+
+impl QueryRegistry {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The hashes bundled with the crate (see [`keys`])
+    #[must_use]
+    pub fn bundled() -> Self {
+        [
+            ("StageScheduleQuery", keys::SCHEDULES),
+            ("GesotownQuery", keys::SPLATNET),
+            ("CoopHistoryQuery", keys::SALMON),
+            ("SaleGearDetailOrderGesotownGearMutation", keys::ORDER),
+            ("FestRecordQuery", keys::SPLATFEST_OVERVIEW),
+            ("DetailFestRecordDetailQuery", keys::SPLATFEST),
+            ("LatestBattleHistoriesQuery", keys::LATEST_BATTLES),
+            ("MyOutfitCommonDataEquipmentsQuery", keys::GEAR),
+        ]
+        .into_iter()
+        .collect()
+    }
+
+    /// Parse a JSON object mapping operation names to hashes
+    ///
+    /// # Errors
+    ///
+    /// If `json` isn't an object whose values are all strings.
+    pub fn from_json(json: &str) -> Result<Self, NsoError> {
+        let queries = serde_json::from_str(json)
+            .map_err(|err| NsoError::InvalidQueryRegistry(err.to_string()))?;
+        Ok(Self { queries })
+    }
+
+    /// Load a JSON object mapping operation names to hashes from `path`
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be read or parsed.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, NsoError> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    /// Add every hash in `other`, replacing the hashes of any operations
+    /// already known
+    pub fn merge(&mut self, other: QueryRegistry) {
+        self.queries.extend(other.queries);
+    }
+
+    /// The hash of the operation `name`, if known
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.queries.get(name).map(String::as_str)
+    }
+
+    /// The name of the operation whose hash is `hash`, if known
+    #[must_use]
+    pub fn name_of(&self, hash: &str) -> Option<&str> {
+        self.iter()
+            .find(|(_, known)| *known == hash)
+            .map(|(name, _)| name)
+    }
+
+    /// Add (or replace) the hash of the operation `name`
+    pub fn insert(&mut self, name: impl Into<String>, hash: impl Into<String>) {
+        self.queries.insert(name.into(), hash.into());
+    }
+
+    /// Every operation name and its hash, in order of name
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.queries
+            .iter()
+            .map(|(name, hash)| (name.as_str(), hash.as_str()))
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.queries.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.queries.is_empty()
+    }
+}
+
+impl<N: Into<String>, H: Into<String>> FromIterator<(N, H)> for QueryRegistry {
+    fn from_iter<T: IntoIterator<Item = (N, H)>>(iter: T) -> Self {
+        Self {
+            queries: iter
+                .into_iter()
+                .map(|(name, hash)| (name.into(), hash.into()))
+                .collect(),
+        }
+    }
+}
+
+impl NsoClient {
+    /// Look SplatNet 3 queries up in `queries` (then in
+    /// [`QueryRegistry::bundled`]), instead of discovering them
+    #[must_use]
+    pub fn with_persisted_queries(self, queries: QueryRegistry) -> Self {
+        let web_view = WebView {
+            queries: Some(queries),
+            ..self.splatoon3_web_view().undiscovered()
+        };
+        self.with_splatoon3_web_view(web_view)
+    }
+
+    /// When SplatNet 3 rejects a persisted query as stale, download the web
+    /// view again and retry the query once if its hash has changed
+    ///
+    /// Queries discovered this way take precedence over those given to
+    /// [`with_persisted_queries`](Self::with_persisted_queries).
+    #[must_use]
+    pub fn with_stale_query_refetch(self, refetch: bool) -> Self {
+        let web_view = WebView {
+            refetch_stale_queries: refetch,
+            ..self.splatoon3_web_view().undiscovered()
+        };
+        self.with_splatoon3_web_view(web_view)
+    }
+}
+
+/// Find every persisted query (`{id: "<hash>", name: "<QueryName>"}`) in the
+/// web view's `main.*.js`
+#[must_use]
+pub fn find_persisted_queries(script: &str) -> QueryRegistry {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN
+        .get_or_init(|| {
+            Regex::new(concat!(
+                r#"\bid:\s*"(?P<hash>[0-9a-f]{32}|[0-9a-f]{64})",\s*"#,
+                r"(?:metadata:\s*\{[^{}]*\},\s*)?",
+                r#"name:\s*"(?P<name>[A-Za-z_][0-9A-Za-z_]*)""#,
+            ))
+            .expect("persisted query pattern is valid")
+        })
+        .captures_iter(script)
+        .map(|captures| (captures["name"].to_string(), captures["hash"].to_string()))
+        .collect()
+}
+
+/// Download the web view and read its persisted queries, without caching them
+///
+/// # Errors
+///
+/// If either request fails.
+pub async fn discover_persisted_queries(
+    client: &NsoClient,
+) -> Result<QueryRegistry, NsoError> {
+    let (_, script) = fetch_main_script(client).await?;
+    Ok(find_persisted_queries(&script))
+}
+
+/// The hash to send for `query`, which is either an operation name or a hash
+///
+/// Hashes are sent as they are. Operation names are looked up in the queries
+/// discovered from the web view (which is only downloaded if no registry was
+/// given to [`NsoClient::with_persisted_queries`]), then in the registry given
+/// to `with_persisted_queries` if any, then in [`QueryRegistry::bundled`].
+///
+/// # Errors
+///
+/// [`NsoError::UnknownQuery`] if `query` isn't a hash, and no hash is known
+/// for it.
+pub async fn persisted_query_hash(
+    query: &str,
+    client: &NsoClient,
+) -> Result<String, NsoError> {
+    Ok(resolve(query, client).await?.0)
+}
+
+/// Like [`persisted_query_hash`], but also returns what had been discovered
+/// from the web view when `query` was looked up
+pub(crate) async fn resolve(
+    query: &str,
+    client: &NsoClient,
+) -> Result<(String, Option<Arc<Discovery>>), NsoError> {
+    let web_view = client.splatoon3_web_view();
+    if is_hash(query) {
+        return Ok((query.to_string(), web_view.discovered().await));
+    }
+    let discovery = match &web_view.queries {
+        Some(_) => web_view.discovered().await,
+        None => Some(web_view.discovery(client).await),
+    };
+    let bundled = QueryRegistry::bundled();
+    let hash = [
+        discovery.as_deref().map(|discovery| &discovery.queries),
+        web_view.queries.as_ref(),
+        Some(&bundled),
+    ]
+    .into_iter()
+    .flatten()
+    .find_map(|queries| queries.get(query))
+    .ok_or_else(|| NsoError::UnknownQuery(query.to_string()))?
+    .to_string();
+    Ok((hash, discovery))
+}
+
+/// The name of the operation whose hash is `hash`, if any registry knows it
+pub(crate) async fn operation_name(hash: &str, client: &NsoClient) -> Option<String> {
+    let web_view = client.splatoon3_web_view();
+    let discovery = web_view.discovered().await;
+    let bundled = QueryRegistry::bundled();
+    let name = [
+        discovery.as_deref().map(|discovery| &discovery.queries),
+        web_view.queries.as_ref(),
+        Some(&bundled),
+    ]
+    .into_iter()
+    .flatten()
+    .find_map(|queries| queries.name_of(hash))
+    .map(str::to_string);
+    name
+}
+
+pub(crate) fn is_hash(query: &str) -> bool {
+    matches!(query.len(), 32 | 64) && query.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn persisted_queries_are_found() {
+        let queries = find_persisted_queries(fixture!("main.js"));
+        assert_eq!(queries.len(), 4);
+        assert_eq!(
+            queries.get("StageScheduleQuery"),
+            Some("730cd98e84f1030d3e9ac86b6f1aae13"),
+        );
+        assert_eq!(
+            queries.get("HomeQuery"),
+            Some("51fc56bbf006caf37728914aa8bc0e2c86a80cf195b4d4027d6822a3623098a8"),
+        );
+        assert_eq!(
+            queries.name_of("b79b7a101a243912754f72437e2ad7e5"),
+            Some("SaleGearDetailOrderGesotownGearMutation"),
+        );
+    }
+
+    #[test]
+    fn persisted_queries_are_missing() {
+        assert!(find_persisted_queries(fixture!("main_no_revision.js")).is_empty());
+        assert!(find_persisted_queries("").is_empty());
+    }
+
+    #[test]
+    fn malformed_persisted_queries_are_ignored() {
+        assert!(find_persisted_queries(fixture!("main_malformed.js")).is_empty());
+    }
+
+    #[test]
+    fn hashes_are_recognised() {
+        assert!(is_hash("730cd98e84f1030d3e9ac86b6f1aae13"));
+        assert!(is_hash(
+            "51fc56bbf006caf37728914aa8bc0e2c86a80cf195b4d4027d6822a3623098a8"
+        ));
+        assert!(!is_hash("StageScheduleQuery"));
+        assert!(!is_hash("730cd98e84f1030d3e9ac86b6f1aae1"));
+        assert!(!is_hash("730cd98e84f1030d3e9ac86b6f1aae1g"));
+        assert!(!is_hash(""));
+    }
+}
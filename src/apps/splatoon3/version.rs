@@ -0,0 +1,159 @@
+//! Discovering the SplatNet 3 web view version
+//!
+//! SplatNet 3 rejects requests whose `X-Web-View-Ver` header is too old, and
+//! the version changes whenever Nintendo updates the web view. The current
+//! version is read from the web view's `main.*.js` (linked from its HTML page)
+//! the first time a client needs it, and cached on the client (and its
+//! clones). If discovery fails, [`DEFAULT_WEB_VIEW_VERSION`] is used instead
+//! until it is retried, five minutes later by default.
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use regex::Regex;
+
+use super::web_view::{fetch_main_script, WebView};
+use crate::{NsoClient, NsoError};
+
+/// The web view version used when discovery fails
+pub const DEFAULT_WEB_VIEW_VERSION: &str = "2.0.0-bd36a652";
+
+impl NsoClient {
+    /// Always send `version` as the SplatNet 3 web view version, instead of
+    /// discovering it
+    #[must_use]
+    pub fn with_web_view_version(self, version: impl Into<String>) -> Self {
+        let web_view = WebView {
+            version: Some(version.into()),
+            ..self.splatoon3_web_view().undiscovered()
+        };
+        self.with_splatoon3_web_view(web_view)
+    }
+
+    /// After failing to download the SplatNet 3 web view, wait `delay`
+    /// (instead of five minutes) before downloading it again
+    #[must_use]
+    pub fn with_web_view_retry_delay(self, delay: Duration) -> Self {
+        let web_view = WebView {
+            retry_delay: Some(delay),
+            ..self.splatoon3_web_view().undiscovered()
+        };
+        self.with_splatoon3_web_view(web_view)
+    }
+}
+
+/// Find the path of the web view's `main.*.js` in its HTML page
+#[must_use]
+pub fn find_main_script(html: &str) -> Option<&str> {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN
+        .get_or_init(|| {
+            Regex::new(concat!(
+                r#"<script (?:defer(?:="?defer"?)? )?"#,
+                r#"src="(/static/js/main\.[0-9a-fA-F]{8}\.js)">"#,
+            ))
+            .expect("main script pattern is valid")
+        })
+        .captures(html)
+        .and_then(|captures| captures.get(1))
+        .map(|path| path.as_str())
+}
+
+/// Find the web view version (for example `2.0.0-bd36a652`) in the web view's
+/// `main.*.js`
+#[must_use]
+pub fn find_web_view_version(script: &str) -> Option<String> {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    let captures = PATTERN
+        .get_or_init(|| {
+            Regex::new(concat!(
+                r"\b(?P<revision>[0-9a-f]{40})",
+                r#"\b[\S]*?void 0[\S]*?"revision_info_not_set"\}"#,
+                r"`,.*?=`(?P<version>\d+\.\d+\.\d+)-",
+            ))
+            .expect("revision pattern is valid")
+        })
+        .captures(script)?;
+    Some(format!(
+        "{}-{}",
+        &captures["version"],
+        &captures["revision"][..8]
+    ))
+}
+
+/// Download the web view and read its version, without caching it
+///
+/// # Errors
+///
+/// If either request fails, or the version cannot be found in the web view
+/// (for example, because Nintendo changed its layout).
+pub async fn discover_web_view_version(client: &NsoClient) -> Result<String, NsoError> {
+    let (script_path, script) = fetch_main_script(client).await?;
+    find_web_view_version(&script).ok_or_else(|| {
+        NsoError::WebViewVersion(format!("no revision info in {script_path}"))
+    })
+}
+
+/// The web view version to send with SplatNet 3 requests
+///
+/// This is the version given to [`NsoClient::with_web_view_version`] if any;
+/// otherwise, it is discovered on first use and cached on `client`. If
+/// discovery fails, [`DEFAULT_WEB_VIEW_VERSION`] is used instead, until the
+/// retry delay (see [`NsoClient::with_web_view_retry_delay`]) has passed.
+pub async fn web_view_version(client: &NsoClient) -> String {
+    let web_view = client.splatoon3_web_view();
+    if let Some(version) = &web_view.version {
+        return version.clone();
+    }
+    web_view
+        .discovery(client)
+        .await
+        .version
+        .clone()
+        .unwrap_or_else(|| DEFAULT_WEB_VIEW_VERSION.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn main_script_is_found() {
+        assert_eq!(
+            find_main_script(fixture!("index.html")),
+            Some("/static/js/main.bd36a652.js"),
+        );
+    }
+
+    #[test]
+    fn main_script_is_missing() {
+        assert_eq!(
+            find_main_script(fixture!("index_no_main_script.html")),
+            None
+        );
+        assert_eq!(find_main_script(""), None);
+    }
+
+    #[test]
+    fn malformed_main_script_is_ignored() {
+        assert_eq!(find_main_script(fixture!("index_malformed.html")), None);
+    }
+
+    #[test]
+    fn web_view_version_is_found() {
+        assert_eq!(
+            find_web_view_version(fixture!("main.js")).as_deref(),
+            Some("2.0.0-bd36a652"),
+        );
+    }
+
+    #[test]
+    fn web_view_version_is_missing() {
+        assert_eq!(find_web_view_version(fixture!("main_no_revision.js")), None);
+        assert_eq!(find_web_view_version(""), None);
+    }
+
+    #[test]
+    fn malformed_web_view_version_is_ignored() {
+        assert_eq!(find_web_view_version(fixture!("main_malformed.js")), None);
+    }
+}
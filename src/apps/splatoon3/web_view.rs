@@ -0,0 +1,192 @@
+//! Downloading the SplatNet 3 web view's `main.*.js`, which both the web view
+//! version and the persisted query hashes are read from
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use reqwest::header::{ACCEPT, COOKIE, DNT, REFERER, UPGRADE_INSECURE_REQUESTS};
+use tokio::sync::Mutex;
+
+use super::queries::{find_persisted_queries, QueryRegistry};
+use super::version::{find_main_script, find_web_view_version};
+use crate::transport::{check_status, send};
+use crate::{NsoClient, NsoError};
+
+/// What was read from the web view
+#[derive(Debug, Default)]
+pub(crate) struct Discovery {
+    pub(crate) version: Option<String>,
+    pub(crate) queries: QueryRegistry,
+    /// When the download failed, if it did
+    pub(crate) failed_at: Option<Instant>,
+}
+
+impl Discovery {
+    fn failed() -> Self {
+        Self {
+            failed_at: Some(Instant::now()),
+            ..Self::default()
+        }
+    }
+}
+
+/// How long to wait before downloading the web view again after a failure
+pub(crate) const DEFAULT_RETRY_DELAY: Duration = Duration::from_secs(5 * 60);
+
+/// A client's pinned web view version and persisted queries, and what was
+/// discovered for whichever of them aren't pinned
+#[derive(Debug, Default)]
+pub(crate) struct WebView {
+    pub(crate) version: Option<String>,
+    pub(crate) queries: Option<QueryRegistry>,
+    /// Whether to download the web view again when a persisted query is
+    /// rejected
+    pub(crate) refetch_stale_queries: bool,
+    /// How long to wait before downloading the web view again after a
+    /// failure, if not [`DEFAULT_RETRY_DELAY`]
+    pub(crate) retry_delay: Option<Duration>,
+    pub(crate) discovered: Mutex<Option<Arc<Discovery>>>,
+}
+
+impl WebView {
+    /// Keep what is pinned, but forget what was discovered
+    pub(crate) fn undiscovered(&self) -> Self {
+        Self {
+            version: self.version.clone(),
+            queries: self.queries.clone(),
+            refetch_stale_queries: self.refetch_stale_queries,
+            retry_delay: self.retry_delay,
+            discovered: Mutex::default(),
+        }
+    }
+
+    /// What was read from the web view, downloading it on first use
+    ///
+    /// If the download fails, nothing is discovered. The failure is cached
+    /// too, so that every request doesn't retry it, but the download is tried
+    /// again once the retry delay has passed.
+    pub(crate) async fn discovery(&self, client: &NsoClient) -> Arc<Discovery> {
+        let retry_delay = self.retry_delay.unwrap_or(DEFAULT_RETRY_DELAY);
+        let mut discovered = self.discovered.lock().await;
+        if let Some(discovery) = &*discovered {
+            let retry = discovery
+                .failed_at
+                .is_some_and(|failed_at| failed_at.elapsed() >= retry_delay);
+            if !retry {
+                return Arc::clone(discovery);
+            }
+        }
+        let discovery = Arc::new(discover(client).await.unwrap_or_else(|_err| {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(
+                error = %_err,
+                "failed to download the SplatNet 3 web view",
+            );
+            Discovery::failed()
+        }));
+        *discovered = Some(Arc::clone(&discovery));
+        discovery
+    }
+
+    /// What was read from the web view, if it has been downloaded
+    pub(crate) async fn discovered(&self) -> Option<Arc<Discovery>> {
+        self.discovered.lock().await.clone()
+    }
+
+    /// Download the web view again, unless it has been downloaded since
+    /// `stale` was read from it
+    ///
+    /// If the download fails, what was discovered before is kept.
+    pub(crate) async fn rediscover(
+        &self,
+        stale: Option<&Arc<Discovery>>,
+        client: &NsoClient,
+    ) -> Arc<Discovery> {
+        let mut discovered = self.discovered.lock().await;
+        match (&*discovered, stale) {
+            (Some(discovery), Some(stale)) if !Arc::ptr_eq(discovery, stale) => {
+                return Arc::clone(discovery);
+            },
+            (Some(discovery), None) => return Arc::clone(discovery),
+            _ => {},
+        }
+        match (discover(client).await, &*discovered) {
+            (Ok(discovery), _) => {
+                let discovery = Arc::new(discovery);
+                *discovered = Some(Arc::clone(&discovery));
+                discovery
+            },
+            (Err(_err), Some(previous)) => {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(
+                    error = %_err,
+                    "failed to download the SplatNet 3 web view again",
+                );
+                Arc::clone(previous)
+            },
+            (Err(_err), None) => {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(
+                    error = %_err,
+                    "failed to download the SplatNet 3 web view",
+                );
+                let discovery = Arc::new(Discovery::failed());
+                *discovered = Some(Arc::clone(&discovery));
+                discovery
+            },
+        }
+    }
+}
+
+async fn discover(client: &NsoClient) -> Result<Discovery, NsoError> {
+    let (_path, script) = fetch_main_script(client).await?;
+    let discovery = Discovery {
+        version: find_web_view_version(&script),
+        queries: find_persisted_queries(&script),
+        failed_at: None,
+    };
+    #[cfg(feature = "tracing")]
+    if discovery.version.is_none() {
+        tracing::warn!(
+            script = %_path,
+            "no revision info in the SplatNet 3 web view",
+        );
+    }
+    Ok(discovery)
+}
+
+/// Download the web view's `main.*.js`, returning its path and contents
+pub(crate) async fn fetch_main_script(
+    client: &NsoClient,
+) -> Result<(String, String), NsoError> {
+    let base_url = &client.endpoints().splatoon3;
+    let request = client
+        .get(base_url)
+        .header(UPGRADE_INSECURE_REQUESTS, "1")
+        .header(ACCEPT, "*/*")
+        .header(DNT, "1")
+        .header("X-AppColorScheme", "DARK")
+        .header("X-Requested-With", "com.nintendo.znca")
+        .header("Sec-Fetch-Site", "none")
+        .header("Sec-Fetch-Mode", "navigate")
+        .header("Sec-Fetch-User", "?1")
+        .header("Sec-Fetch-Dest", "document")
+        .header(COOKIE, "_dnt=1");
+    let page = send("splatoon3.web_view", request, client).await?;
+    let page = check_status(page).await?.text().await?;
+    let script_path = find_main_script(&page)
+        .ok_or_else(|| {
+            NsoError::WebViewVersion("no main script in the web view".to_string())
+        })?
+        .to_string();
+    let request = client
+        .get(format!("{base_url}{script_path}"))
+        .header(ACCEPT, "*/*")
+        .header("X-Requested-With", "com.nintendo.znca")
+        .header("Sec-Fetch-Site", "same-origin")
+        .header("Sec-Fetch-Mode", "no-cors")
+        .header("Sec-Fetch-Dest", "script")
+        .header(REFERER, format!("{base_url}/"));
+    let script = send("splatoon3.main_script", request, client).await?;
+    let script = check_status(script).await?.text().await?;
+    Ok((script_path, script))
+}
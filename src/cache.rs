@@ -0,0 +1,152 @@
+//! A token cache shared between processes
+//!
+//! Processes sharing a cache take an advisory lock on each layer of the login
+//! chain before refreshing it. A process which has to wait for the lock
+//! re-reads the cache once it has it, and reuses the token the other process
+//! just wrote instead of requesting its own.
+use std::fs::{self, File, OpenOptions};
+use std::io;
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+
+use fs2::FileExt;
+
+use crate::{Credentials, NsoError};
+
+/// A layer of the login chain, each refreshed under its own lock
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheLayer {
+    /// The access and ID tokens
+    Tokens,
+    /// The Coral login token
+    LoginToken,
+    /// The web token for a game
+    WebToken(u64),
+    /// The Splatoon 3 `bullet_token`
+    BulletToken,
+    /// The Splatoon 2 `iksm_session`
+    IksmSession,
+}
+
+impl CacheLayer {
+    fn lock_name(self) -> String {
+        match self {
+            Self::Tokens => "tokens".to_string(),
+            Self::LoginToken => "login".to_string(),
+            Self::WebToken(game_id) => format!("web-{game_id}"),
+            Self::BulletToken => "bullet".to_string(),
+            Self::IksmSession => "iksm".to_string(),
+        }
+    }
+}
+
+/// Credentials stored in a file which several processes may read and refresh
+/// at once
+///
+/// Lock files are kept next to the cache file, named after it. On Unix, the
+/// cache file and its lock files are only accessible to the user, so that
+/// other users can neither read the tokens nor hold the locks.
+#[derive(Clone, Debug)]
+pub struct TokenCache {
+    path: PathBuf,
+}
+
+/// An exclusive lock on one layer of a [`TokenCache`], released when dropped
+#[derive(Debug)]
+pub struct CacheLock {
+    _file: File,
+}
+
+impl TokenCache {
+    /// Use the credentials file at `path`, creating it when first written
+    #[must_use]
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn lock_path(&self, name: &str) -> PathBuf {
+        let mut lock_path = self.path.as_os_str().to_owned();
+        lock_path.push(format!(".{name}.lock"));
+        lock_path.into()
+    }
+
+    /// Wait until no other process is refreshing `layer`, then stop any other
+    /// process from doing so until the lock is dropped
+    ///
+    /// # Errors
+    ///
+    /// If the lock file cannot be opened or locked.
+    pub async fn lock(&self, layer: CacheLayer) -> Result<CacheLock, NsoError> {
+        let path = self.lock_path(&layer.lock_name());
+        let file = tokio::task::spawn_blocking(move || lock_file(&path, true))
+            .await
+            .map_err(io::Error::other)??;
+        Ok(CacheLock { _file: file })
+    }
+
+    /// Read the cached credentials
+    ///
+    /// If nothing has been cached yet, empty credentials are returned.
+    ///
+    /// # Errors
+    ///
+    /// If the cache file cannot be read or parsed.
+    pub async fn load(&self) -> Result<Credentials, NsoError> {
+        let path = self.path.clone();
+        let data_lock = self.lock_path("data");
+        tokio::task::spawn_blocking(move || {
+            let _lock = lock_file(&data_lock, false)?;
+            read(&path)
+        })
+        .await
+        .map_err(io::Error::other)?
+    }
+
+    /// Merge `credentials` into the cache (see [`Credentials::merge`]), so
+    /// that tokens written by other processes in the meantime aren't lost
+    ///
+    /// # Errors
+    ///
+    /// If the cache file cannot be read, parsed or written.
+    pub async fn store(&self, credentials: Credentials) -> Result<(), NsoError> {
+        let path = self.path.clone();
+        let data_lock = self.lock_path("data");
+        tokio::task::spawn_blocking(move || {
+            let _lock = lock_file(&data_lock, true)?;
+            let mut cached = read(&path)?;
+            cached.merge(credentials);
+            cached.save(&path)
+        })
+        .await
+        .map_err(io::Error::other)?
+    }
+}
+
+fn lock_file(path: &Path, exclusive: bool) -> Result<File, NsoError> {
+    let mut options = OpenOptions::new();
+    options.create(true).truncate(false).write(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let file = options.open(path)?;
+    // Called through the trait, as newer versions of `std` have an inherent
+    // `File::lock_shared` which would otherwise take precedence
+    if exclusive {
+        FileExt::lock_exclusive(&file)?;
+    } else {
+        FileExt::lock_shared(&file)?;
+    }
+    Ok(file)
+}
+
+fn read(path: &Path) -> Result<Credentials, NsoError> {
+    match fs::metadata(path) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Credentials::default()),
+        _ => Credentials::load(path),
+    }
+}
@@ -0,0 +1,356 @@
+//! Recording requests and responses, and replaying them later
+//!
+//! A [`Recorder`] captures every request made through an [`NsoClient`] into a
+//! [`Cassette`], which can be saved as JSON. A client built with
+//! [`NsoClient::with_replay`] serves responses from a cassette instead of
+//! sending requests, so a failing run can be reproduced without a Nintendo
+//! Account or network access.
+//!
+//! Secrets are scrubbed before anything is recorded: request headers are
+//! dropped entirely, only `Content-Type` and `Set-Cookie` response headers are
+//! kept (with cookie values replaced), and the values of token fields (see
+//! [`SECRET_FIELDS`]) in JSON and form bodies are replaced with
+//! [`REDACTED`].
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use reqwest::header::{HeaderValue, CONTENT_TYPE, SET_COOKIE};
+use reqwest::{Request, Response, ResponseBuilderExt, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::credentials::write_private;
+use crate::{NsoClient, NsoError};
+
+/// The version of the cassette format written by this version of the crate
+pub const CASSETTE_VERSION: u32 = 1;
+/// What secrets are replaced with
+pub const REDACTED: &str = "<redacted>";
+/// Body fields whose values are always scrubbed
+pub const SECRET_FIELDS: [&str; 14] = [
+    "session_token",
+    "session_token_code",
+    "session_token_code_verifier",
+    "access_token",
+    "id_token",
+    "accessToken",
+    "idToken",
+    "bulletToken",
+    "registrationToken",
+    "naIdToken",
+    "token",
+    "f",
+    "iksm_session",
+    "_gtoken",
+];
+
+/// A request, as recorded
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub url: String,
+    /// The scrubbed body, if it was text
+    pub body: Option<String>,
+}
+
+/// A response, as recorded
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    pub status: u16,
+    /// The `Content-Type` and (scrubbed) `Set-Cookie` headers
+    pub headers: Vec<(String, String)>,
+    /// The scrubbed body
+    pub body: String,
+}
+
+/// One request and the response it got
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Interaction {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+/// A sequence of recorded interactions
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Cassette {
+    pub version: u32,
+    pub interactions: Vec<Interaction>,
+}
+
+impl Default for Cassette {
+    fn default() -> Self {
+        Self {
+            version: CASSETTE_VERSION,
+            interactions: Vec::new(),
+        }
+    }
+}
+
+impl Cassette {
+    /// # Errors
+    ///
+    /// If the file cannot be read or parsed, or was written by a newer
+    /// version of the crate.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, NsoError> {
+        let cassette: Self = serde_json::from_str(&fs::read_to_string(path)?)
+            .map_err(|err| NsoError::InvalidCassette(err.to_string()))?;
+        if cassette.version > CASSETTE_VERSION {
+            return Err(NsoError::InvalidCassette(format!(
+                "unsupported version {}",
+                cassette.version,
+            )));
+        }
+        Ok(cassette)
+    }
+
+    /// Save the cassette to `path`
+    ///
+    /// Scrubbing doesn't catch every personal detail (such as the user's
+    /// friend code), so on Unix the file is only readable by the user.
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be written.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), NsoError> {
+        let contents = serde_json::to_string_pretty(self)
+            .map_err(|err| NsoError::InvalidCassette(err.to_string()))?;
+        write_private(path.as_ref(), contents.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// Captures every request made through a client
+///
+/// See [`NsoClient::with_recorder`].
+#[derive(Debug, Default)]
+pub struct Recorder {
+    interactions: Mutex<Vec<Interaction>>,
+}
+
+impl Recorder {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Everything recorded so far
+    ///
+    /// # Panics
+    ///
+    /// If a thread panicked while recording.
+    #[must_use]
+    pub fn cassette(&self) -> Cassette {
+        Cassette {
+            version: CASSETTE_VERSION,
+            interactions: self.interactions.lock().unwrap().clone(),
+        }
+    }
+
+    /// Save everything recorded so far to `path`
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be written.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), NsoError> {
+        self.cassette().save(path)
+    }
+}
+
+/// Serves responses from a cassette, each at most once, to requests with the
+/// same method, URL and (scrubbed) body
+///
+/// If no unused interaction has the same body, the first unused one with the
+/// same method and URL is served, since bodies include timestamps.
+#[derive(Debug)]
+pub(crate) struct Player {
+    interactions: Mutex<Vec<Option<Interaction>>>,
+}
+
+/// Where a client's requests go
+#[derive(Clone, Debug, Default)]
+pub(crate) enum Transport {
+    #[default]
+    Live,
+    Record(Arc<Recorder>),
+    Replay(Arc<Player>),
+}
+
+impl NsoClient {
+    /// Record every request made through this client (and its clones)
+    #[must_use]
+    pub fn with_recorder(self, recorder: Recorder) -> Self {
+        self.with_transport(Transport::Record(Arc::new(recorder)))
+    }
+
+    /// Serve every request from `cassette` instead of sending it
+    #[must_use]
+    pub fn with_replay(self, cassette: Cassette) -> Self {
+        self.with_transport(Transport::Replay(Arc::new(Player {
+            interactions: Mutex::new(
+                cassette.interactions.into_iter().map(Some).collect(),
+            ),
+        })))
+    }
+
+    /// The recorder set by [`with_recorder`](Self::with_recorder), if any
+    #[must_use]
+    pub fn recorder(&self) -> Option<&Recorder> {
+        match self.transport() {
+            Transport::Record(recorder) => Some(recorder),
+            _ => None,
+        }
+    }
+}
+
+/// Send `request` through the client's transport
+pub(crate) async fn execute(
+    request: Request,
+    client: &NsoClient,
+) -> Result<Response, NsoError> {
+    match client.transport() {
+        Transport::Live => Ok(client.http().execute(request).await?),
+        Transport::Record(recorder) => {
+            let recorded_request = record_request(&request);
+            let response = client.http().execute(request).await?;
+            let status = response.status().as_u16();
+            let url = response.url().clone();
+            // The caller gets the real cookies; only the recording is scrubbed
+            let live_headers = kept_headers(&response, false);
+            let headers = kept_headers(&response, true);
+            let body = response.text().await?;
+            let live = replay(
+                &RecordedResponse {
+                    status,
+                    headers: live_headers,
+                    body: body.clone(),
+                },
+                url,
+            )?;
+            recorder.interactions.lock().unwrap().push(Interaction {
+                request: recorded_request,
+                response: RecordedResponse {
+                    status,
+                    headers,
+                    body: scrub_body(&body),
+                },
+            });
+            Ok(live)
+        },
+        Transport::Replay(player) => {
+            let url = request.url().clone();
+            let request = record_request(&request);
+            let mut interactions = player.interactions.lock().unwrap();
+            let same_url = |interaction: &Interaction| {
+                interaction.request.method == request.method
+                    && interaction.request.url == request.url
+            };
+            let position = interactions
+                .iter()
+                .position(|interaction| {
+                    interaction.as_ref().is_some_and(|interaction| {
+                        same_url(interaction) && interaction.request == request
+                    })
+                })
+                .or_else(|| {
+                    interactions.iter().position(|interaction| {
+                        interaction.as_ref().is_some_and(same_url)
+                    })
+                })
+                .ok_or_else(|| NsoError::ReplayMiss {
+                    method: request.method.clone(),
+                    url: request.url.clone(),
+                })?;
+            let interaction = interactions[position]
+                .take()
+                .expect("interaction is unused");
+            replay(&interaction.response, url)
+        },
+    }
+}
+
+fn record_request(request: &Request) -> RecordedRequest {
+    RecordedRequest {
+        method: request.method().to_string(),
+        url: request.url().to_string(),
+        body: request
+            .body()
+            .and_then(|body| body.as_bytes())
+            .and_then(|body| std::str::from_utf8(body).ok())
+            .map(scrub_body),
+    }
+}
+
+/// The `Content-Type` and `Set-Cookie` headers of a response, optionally with
+/// the cookies' values (and attributes) scrubbed
+fn kept_headers(response: &Response, scrub: bool) -> Vec<(String, String)> {
+    let mut headers = Vec::new();
+    for (name, value) in response.headers() {
+        let Ok(value) = value.to_str() else {
+            continue;
+        };
+        if name == CONTENT_TYPE {
+            headers.push((name.to_string(), value.to_string()));
+        } else if name == SET_COOKIE {
+            let value = match value.split_once('=') {
+                Some((cookie, _)) if scrub => format!("{cookie}={REDACTED}"),
+                _ => value.to_string(),
+            };
+            headers.push((name.to_string(), value));
+        }
+    }
+    headers
+}
+
+/// Build a response from a recording, as if it came from `url`
+fn replay(recorded: &RecordedResponse, url: Url) -> Result<Response, NsoError> {
+    let mut response = http::Response::builder().status(recorded.status).url(url);
+    for (name, value) in &recorded.headers {
+        let value = HeaderValue::from_str(value)
+            .map_err(|err| NsoError::InvalidCassette(err.to_string()))?;
+        response = response.header(name, value);
+    }
+    let response = response
+        .body(recorded.body.clone())
+        .map_err(|err| NsoError::InvalidCassette(err.to_string()))?;
+    Ok(response.into())
+}
+
+/// Replace the values of [`SECRET_FIELDS`] in a JSON or form body
+fn scrub_body(body: &str) -> String {
+    if let Ok(mut json) = serde_json::from_str::<Value>(body) {
+        scrub_json(&mut json);
+        return json.to_string();
+    }
+    let is_form = !body.is_empty()
+        && body.split('&').all(|pair| pair.contains('='))
+        && !body.contains(char::is_whitespace);
+    if !is_form {
+        return body.to_string();
+    }
+    let mut scrubbed = url::form_urlencoded::Serializer::new(String::new());
+    for (key, value) in url::form_urlencoded::parse(body.as_bytes()) {
+        if SECRET_FIELDS.contains(&&*key) {
+            scrubbed.append_pair(&key, REDACTED);
+        } else {
+            scrubbed.append_pair(&key, &value);
+        }
+    }
+    scrubbed.finish()
+}
+
+fn scrub_json(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object {
+                if SECRET_FIELDS.contains(&&**key) && value.is_string() {
+                    *value = Value::String(REDACTED.to_string());
+                } else {
+                    scrub_json(value);
+                }
+            }
+        },
+        Value::Array(values) => values.iter_mut().for_each(scrub_json),
+        _ => {},
+    }
+}
@@ -0,0 +1,91 @@
+use std::sync::Arc;
+
+use reqwest::{Client, IntoUrl, RequestBuilder};
+
+use crate::cassette::Transport;
+use crate::splatoon3::web_view::WebView;
+use crate::Endpoints;
+
+/// The HTTP client used for every request, along with the [`Endpoints`] it
+/// should send them to
+///
+/// Cloning an `NsoClient` is cheap, and clones share a connection pool (and
+/// cookie store, if enabled).
+#[derive(Debug, Clone, Default)]
+pub struct NsoClient {
+    http: Client,
+    endpoints: Arc<Endpoints>,
+    transport: Transport,
+    splatoon3_web_view: Arc<WebView>,
+}
+
+impl NsoClient {
+    /// Wrap `http`, using the default [`Endpoints`]
+    #[must_use]
+    pub fn new(http: Client) -> Self {
+        Self {
+            http,
+            endpoints: Arc::default(),
+            transport: Transport::Live,
+            splatoon3_web_view: Arc::default(),
+        }
+    }
+
+    /// Send requests to `endpoints` instead
+    #[must_use]
+    pub fn with_endpoints(mut self, endpoints: Endpoints) -> Self {
+        self.endpoints = Arc::new(endpoints);
+        self.splatoon3_web_view = Arc::new(self.splatoon3_web_view.undiscovered());
+        self
+    }
+
+    /// Send requests with `http` instead, keeping everything else (such as
+    /// the endpoints, and any recording or replaying)
+    #[must_use]
+    pub fn with_http(mut self, http: Client) -> Self {
+        self.http = http;
+        self
+    }
+
+    #[must_use]
+    pub fn http(&self) -> &Client {
+        &self.http
+    }
+
+    #[must_use]
+    pub fn endpoints(&self) -> &Endpoints {
+        &self.endpoints
+    }
+
+    pub(crate) fn with_transport(mut self, transport: Transport) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    pub(crate) fn transport(&self) -> &Transport {
+        &self.transport
+    }
+
+    pub(crate) fn with_splatoon3_web_view(mut self, web_view: WebView) -> Self {
+        self.splatoon3_web_view = Arc::new(web_view);
+        self
+    }
+
+    pub(crate) fn splatoon3_web_view(&self) -> &WebView {
+        &self.splatoon3_web_view
+    }
+
+    pub(crate) fn get(&self, url: impl IntoUrl) -> RequestBuilder {
+        self.http.get(url)
+    }
+
+    pub(crate) fn post(&self, url: impl IntoUrl) -> RequestBuilder {
+        self.http.post(url)
+    }
+}
+
+impl From<Client> for NsoClient {
+    fn from(http: Client) -> Self {
+        Self::new(http)
+    }
+}
@@ -0,0 +1,185 @@
+//! Friend codes, and finding and adding users by them
+use std::fmt;
+use std::str::FromStr;
+
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{coral, CoralToken, NsoClient, NsoError};
+
+/// A Nintendo Switch friend code, such as `SW-1234-5678-9012`
+///
+/// Parses with or without the `SW-` prefix (and with or without dashes), and
+/// displays with it. Coral sends and receives friend codes without the
+/// prefix, which is how they're serialised.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FriendCode([u8; 12]);
+
+impl FriendCode {
+    /// The code without the `SW-` prefix, as used by Coral
+    #[must_use]
+    pub fn digits(&self) -> String {
+        let digits: String = self
+            .0
+            .iter()
+            .map(|digit| char::from(b'0' + digit))
+            .collect();
+        format!("{}-{}-{}", &digits[..4], &digits[4..8], &digits[8..])
+    }
+}
+
+impl FromStr for FriendCode {
+    type Err = NsoError;
+
+    fn from_str(code: &str) -> Result<Self, Self::Err> {
+        let invalid = || NsoError::InvalidFriendCode(code.to_string());
+        let trimmed = code.trim();
+        let unprefixed = trimmed
+            .strip_prefix("SW-")
+            .or_else(|| trimmed.strip_prefix("sw-"))
+            .unwrap_or(trimmed);
+        let groups: Vec<&str> = if unprefixed.contains('-') {
+            unprefixed.split('-').collect()
+        } else {
+            vec![unprefixed]
+        };
+        let well_formed = match groups.as_slice() {
+            [digits] => digits.len() == 12,
+            groups => groups.len() == 3 && groups.iter().all(|group| group.len() == 4),
+        };
+        if !well_formed {
+            return Err(invalid());
+        }
+        let mut digits = [0; 12];
+        for (digit, char) in digits.iter_mut().zip(groups.concat().chars()) {
+            *digit = char
+                .to_digit(10)
+                .and_then(|value| u8::try_from(value).ok())
+                .ok_or_else(invalid)?;
+        }
+        Ok(Self(digits))
+    }
+}
+
+impl fmt::Display for FriendCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SW-{}", self.digits())
+    }
+}
+
+impl fmt::Debug for FriendCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "FriendCode({self})")
+    }
+}
+
+impl Serialize for FriendCode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.digits())
+    }
+}
+
+impl<'de> Deserialize<'de> for FriendCode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// A link which adds the user as a friend, as returned by
+/// [`create_friend_code_url`]
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FriendCodeUrl {
+    pub url: String,
+    pub friend_code: FriendCode,
+}
+
+/// A user found by [`get_user_by_friend_code`]
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FriendCodeUser {
+    /// The user's Coral user ID
+    pub id: u64,
+    /// The user's Nintendo Switch account ID, used to send them a friend
+    /// request
+    pub nsa_id: String,
+    pub name: String,
+    pub image_uri: String,
+}
+
+/// Create a link which adds the user as a friend
+///
+/// # Errors
+///
+/// If the request to Nintendo fails, or if Coral responds with an error (for
+/// example, if the login token has expired)
+pub async fn create_friend_code_url(
+    login_token: &CoralToken,
+    client: &NsoClient,
+) -> Result<FriendCodeUrl, NsoError> {
+    #[derive(Serialize)]
+    struct Parameter {}
+    coral::call(
+        "/v3/Friend/CreateFriendCodeUrl",
+        Some(login_token.expose()),
+        Parameter {},
+        client,
+    )
+    .await
+}
+
+/// Find the user with the friend code `friend_code`
+///
+/// # Errors
+///
+/// If the request to Nintendo fails, or if Coral responds with an error (for
+/// example, if no user has the friend code)
+pub async fn get_user_by_friend_code(
+    friend_code: &FriendCode,
+    login_token: &CoralToken,
+    client: &NsoClient,
+) -> Result<FriendCodeUser, NsoError> {
+    #[derive(Serialize)]
+    #[allow(non_snake_case)]
+    struct Parameter<'a> {
+        friendCode: &'a FriendCode,
+    }
+    coral::call(
+        "/v3/Friend/GetUserByFriendCode",
+        Some(login_token.expose()),
+        Parameter {
+            friendCode: friend_code,
+        },
+        client,
+    )
+    .await
+}
+
+/// Send a friend request to the user with the Nintendo Switch account ID
+/// `nsa_id` (see [`FriendCodeUser::nsa_id`])
+///
+/// # Errors
+///
+/// If the request to Nintendo fails, or if Coral responds with an error (for
+/// example, if the user can't be sent a friend request)
+pub async fn send_friend_request(
+    nsa_id: &str,
+    login_token: &CoralToken,
+    client: &NsoClient,
+) -> Result<(), NsoError> {
+    #[derive(Serialize)]
+    #[allow(non_snake_case)]
+    struct Parameter<'a> {
+        nsaId: &'a str,
+    }
+    coral::call::<_, IgnoredAny>(
+        "/v3/FriendRequest/Create",
+        Some(login_token.expose()),
+        Parameter { nsaId: nsa_id },
+        client,
+    )
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,234 @@
+//! The user's friends, and what they're playing
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{coral, CoralToken, NsoClient, NsoError};
+
+/// A friend, as returned by [`get_friend_list`]
+///
+/// Timestamps are in seconds since the Unix epoch.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Friend {
+    /// The friend's Coral user ID
+    pub id: u64,
+    /// The friend's Nintendo Switch account ID
+    pub nsa_id: String,
+    pub name: String,
+    pub image_uri: String,
+    /// Whether the user has marked the friend as a best friend
+    #[serde(rename = "isFavoriteFriend")]
+    pub is_favourite: bool,
+    /// Whether the friend has used the Nintendo Switch Online app
+    pub is_service_user: bool,
+    pub friend_created_at: u64,
+    pub presence: Presence,
+}
+
+/// Whether a friend is online, and what they're playing
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Presence {
+    pub state: PresenceState,
+    /// When the presence last changed
+    pub updated_at: u64,
+    /// When the friend last went offline, or `0` if unknown
+    pub logout_at: u64,
+    /// The game being played, if any
+    #[serde(deserialize_with = "game_or_empty")]
+    pub game: Option<PresenceGame>,
+}
+
+/// A friend's online state
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[non_exhaustive]
+pub enum PresenceState {
+    Offline,
+    /// Online, but the console is asleep
+    Inactive,
+    /// Online, but not playing a game (for example, on the HOME Menu)
+    Online,
+    Playing,
+    /// Any state Coral adds later
+    #[serde(other)]
+    Unknown,
+}
+
+impl PresenceState {
+    /// Whether the friend is online (even if inactive)
+    #[must_use]
+    pub fn is_online(self) -> bool {
+        matches!(self, Self::Inactive | Self::Online | Self::Playing)
+    }
+}
+
+/// The game a friend is (or was last) playing
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PresenceGame {
+    /// The game's title
+    pub name: String,
+    pub image_uri: String,
+    /// The game's page on the Nintendo eShop website
+    pub shop_uri: String,
+    /// The friend's total play time, in minutes
+    pub total_play_time: u64,
+    /// When the friend first played the game
+    pub first_played_at: u64,
+    /// The game's own description of what the friend is doing, if any
+    #[serde(default)]
+    pub sys_description: String,
+}
+
+/// Coral sends `{}` rather than `null` when no game is being played
+///
+/// Any other object must be a valid [`PresenceGame`].
+fn game_or_empty<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<PresenceGame>, D::Error> {
+    match Option::<Map<String, Value>>::deserialize(deserializer)? {
+        Some(game) if !game.is_empty() => {
+            PresenceGame::deserialize(Value::Object(game))
+                .map(Some)
+                .map_err(serde::de::Error::custom)
+        },
+        _ => Ok(None),
+    }
+}
+
+/// Get the user's friends, along with their presence
+///
+/// # Errors
+///
+/// If the request to Nintendo fails, or if Coral responds with an error (for
+/// example, if the login token has expired)
+pub async fn get_friend_list(
+    login_token: &CoralToken,
+    client: &NsoClient,
+) -> Result<Vec<Friend>, NsoError> {
+    #[derive(Serialize)]
+    struct Parameter {}
+    #[derive(Deserialize)]
+    struct Result {
+        friends: Vec<Friend>,
+    }
+    let result = coral::call::<_, Result>(
+        "/v3/Friend/List",
+        Some(login_token.expose()),
+        Parameter {},
+        client,
+    )
+    .await?;
+    Ok(result.friends)
+}
+
+/// Mark the friend with the Nintendo Switch account ID `nsa_id` as a best
+/// friend
+///
+/// # Errors
+///
+/// If the request to Nintendo fails, or if Coral responds with an error (for
+/// example, if the user isn't a friend)
+pub async fn favourite_friend(
+    nsa_id: &str,
+    login_token: &CoralToken,
+    client: &NsoClient,
+) -> Result<(), NsoError> {
+    friend_mutation("/v3/Friend/Favorite/Create", nsa_id, login_token, client).await
+}
+
+/// Stop marking the friend with the Nintendo Switch account ID `nsa_id` as a
+/// best friend
+///
+/// # Errors
+///
+/// If the request to Nintendo fails, or if Coral responds with an error (for
+/// example, if the user isn't a friend)
+pub async fn unfavourite_friend(
+    nsa_id: &str,
+    login_token: &CoralToken,
+    client: &NsoClient,
+) -> Result<(), NsoError> {
+    friend_mutation("/v3/Friend/Favorite/Delete", nsa_id, login_token, client).await
+}
+
+/// Remove the friend with the Nintendo Switch account ID `nsa_id`
+///
+/// # Errors
+///
+/// If the request to Nintendo fails, or if Coral responds with an error (for
+/// example, if the user isn't a friend)
+pub async fn delete_friend(
+    nsa_id: &str,
+    login_token: &CoralToken,
+    client: &NsoClient,
+) -> Result<(), NsoError> {
+    friend_mutation("/v3/Friend/Delete", nsa_id, login_token, client).await
+}
+
+/// Call a Coral endpoint which takes a friend's NSA ID and returns nothing
+async fn friend_mutation(
+    path: &'static str,
+    nsa_id: &str,
+    login_token: &CoralToken,
+    client: &NsoClient,
+) -> Result<(), NsoError> {
+    #[derive(Serialize)]
+    #[allow(non_snake_case)]
+    struct Parameter<'a> {
+        nsaId: &'a str,
+    }
+    coral::call::<_, IgnoredAny>(
+        path,
+        Some(login_token.expose()),
+        Parameter { nsaId: nsa_id },
+        client,
+    )
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn presence(game: Value) -> serde_json::Result<Presence> {
+        serde_json::from_value(json!({
+            "state": "PLAYING",
+            "updatedAt": 1_700_000_000,
+            "logoutAt": 0,
+            "game": game,
+        }))
+    }
+
+    #[test]
+    fn empty_game_is_none() {
+        assert_eq!(presence(json!({})).unwrap().game, None);
+        assert_eq!(presence(Value::Null).unwrap().game, None);
+    }
+
+    #[test]
+    fn game_is_parsed() {
+        let game = presence(json!({
+            "name": "Splatoon 3",
+            "imageUri": "https://example.com/splatoon3.jpg",
+            "shopUri": "https://example.com/splatoon3",
+            "totalPlayTime": 6000,
+            "firstPlayedAt": 1_662_000_000,
+        }))
+        .unwrap()
+        .game
+        .unwrap();
+        assert_eq!(game.name, "Splatoon 3");
+        assert_eq!(game.sys_description, "");
+    }
+
+    #[test]
+    fn malformed_game_is_an_error() {
+        assert!(presence(json!({"name": "Splatoon 3"})).is_err());
+    }
+}
@@ -0,0 +1,281 @@
+//! Helpers for Coral, the API behind the Nintendo Switch Online app
+//! (`api-lp1.znc.srv.nintendo.net`)
+use std::fmt;
+
+use reqwest::header::{
+    ACCEPT,
+    ACCEPT_ENCODING,
+    ACCEPT_LANGUAGE,
+    AUTHORIZATION,
+    CONNECTION,
+    USER_AGENT,
+};
+use reqwest::Response;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::transport::{json, send};
+use crate::{NsoClient, NsoError, NSO_USER_AGENT, NSO_VERSION};
+
+mod friend_code;
+mod friends;
+mod user;
+pub use friend_code::{
+    create_friend_code_url,
+    get_user_by_friend_code,
+    send_friend_request,
+    FriendCode,
+    FriendCodeUrl,
+    FriendCodeUser,
+};
+pub use friends::{
+    delete_friend,
+    favourite_friend,
+    get_friend_list,
+    unfavourite_friend,
+    Friend,
+    Presence,
+    PresenceGame,
+    PresenceState,
+};
+pub use user::{
+    get_current_user,
+    get_permissions,
+    update_presence_permission,
+    CurrentUser,
+    FriendCodeLink,
+    PresencePermission,
+    UserLinks,
+    UserPermissions,
+};
+
+/// A status code returned by Coral in place of a result
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum CoralErrorCode {
+    /// `9400`: the request was malformed
+    BadRequest,
+    /// `9401`: the endpoint doesn't accept this HTTP method
+    MethodNotAllowed,
+    /// `9402`: the endpoint doesn't exist
+    ResourceNotFound,
+    /// `9403`: the token (or the f-token and timestamp sent alongside it) was
+    /// rejected
+    InvalidToken,
+    /// `9404`: the login token has expired, and a new one must be requested
+    TokenExpired,
+    /// `9405`: the request is not allowed
+    Forbidden,
+    /// `9406`: the user may not use this service (for example, if they don't
+    /// own the game behind a web service)
+    Unauthorised,
+    /// `9407`: the Nintendo Account is not linked to a Nintendo Switch Online
+    /// account
+    NsaNotLinked,
+    /// `9409`: the web service ID is not supported
+    ApplicationIdNotSupported,
+    /// `9427`: the NSO version being mocked is too old, and
+    /// [`NSO_VERSION`] needs bumping
+    UpgradeRequired,
+    /// `9428`: the account has been disabled
+    AccountDisabled,
+    /// `9450`: a Nintendo Switch Online membership is required
+    MembershipRequired,
+    /// `9500`: Coral failed internally
+    InternalServerError,
+    /// `9501`: Coral is temporarily unavailable
+    ServiceUnavailable,
+    /// `9511`: Coral is down for maintenance
+    Maintenance,
+    /// Any other status code
+    Unknown(u32),
+}
+
+impl CoralErrorCode {
+    /// Map a Coral `status` to its error code
+    #[must_use]
+    pub fn from_status(status: u32) -> Self {
+        match status {
+            9400 => Self::BadRequest,
+            9401 => Self::MethodNotAllowed,
+            9402 => Self::ResourceNotFound,
+            9403 => Self::InvalidToken,
+            9404 => Self::TokenExpired,
+            9405 => Self::Forbidden,
+            9406 => Self::Unauthorised,
+            9407 => Self::NsaNotLinked,
+            9409 => Self::ApplicationIdNotSupported,
+            9427 => Self::UpgradeRequired,
+            9428 => Self::AccountDisabled,
+            9450 => Self::MembershipRequired,
+            9500 => Self::InternalServerError,
+            9501 => Self::ServiceUnavailable,
+            9511 => Self::Maintenance,
+            status => Self::Unknown(status),
+        }
+    }
+
+    /// The raw `status` sent by Coral
+    #[must_use]
+    pub fn status(self) -> u32 {
+        match self {
+            Self::BadRequest => 9400,
+            Self::MethodNotAllowed => 9401,
+            Self::ResourceNotFound => 9402,
+            Self::InvalidToken => 9403,
+            Self::TokenExpired => 9404,
+            Self::Forbidden => 9405,
+            Self::Unauthorised => 9406,
+            Self::NsaNotLinked => 9407,
+            Self::ApplicationIdNotSupported => 9409,
+            Self::UpgradeRequired => 9427,
+            Self::AccountDisabled => 9428,
+            Self::MembershipRequired => 9450,
+            Self::InternalServerError => 9500,
+            Self::ServiceUnavailable => 9501,
+            Self::Maintenance => 9511,
+            Self::Unknown(status) => status,
+        }
+    }
+
+    /// Whether logging in again (with a fresh f-token) may fix the error
+    #[must_use]
+    pub fn requires_relogin(self) -> bool {
+        matches!(self, Self::InvalidToken | Self::TokenExpired)
+    }
+
+    /// Whether [`NSO_VERSION`] is too old for Coral
+    #[must_use]
+    pub fn requires_upgrade(self) -> bool {
+        self == Self::UpgradeRequired
+    }
+
+    /// Whether the error is on Coral's side, and the request may succeed if
+    /// retried later
+    #[must_use]
+    pub fn is_transient(self) -> bool {
+        matches!(
+            self,
+            Self::InternalServerError | Self::ServiceUnavailable | Self::Maintenance
+        )
+    }
+}
+
+impl fmt::Display for CoralErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.status())
+    }
+}
+
+/// An error payload returned by Coral
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("Coral error {code}: {message}")]
+pub struct CoralError {
+    pub code: CoralErrorCode,
+    /// The human-readable `errorMessage`
+    pub message: String,
+    /// The `correlationId`, which identifies the request in Nintendo's logs
+    pub correlation_id: Option<String>,
+}
+
+/// Start a request to a Coral endpoint, with the headers sent by the NSO app
+///
+/// `token` is the login token, or `None` for endpoints called before logging
+/// in.
+pub(crate) fn request(
+    path: &str,
+    token: Option<&str>,
+    client: &NsoClient,
+) -> reqwest::RequestBuilder {
+    client
+        .post(format!("{}{path}", client.endpoints().coral))
+        .header(ACCEPT_LANGUAGE, "en-US")
+        .header(USER_AGENT, NSO_USER_AGENT)
+        .header(ACCEPT, "application/json")
+        .header("X-ProductVersion", NSO_VERSION)
+        .header(CONNECTION, "Keep-Alive")
+        .header(
+            AUTHORIZATION,
+            token.map_or_else(
+                || "Bearer".to_string(),
+                |token| format!("Bearer {token}"),
+            ),
+        )
+        .header("X-Platform", "Android")
+        .header(ACCEPT_ENCODING, "gzip")
+}
+
+/// Call a Coral endpoint with `{"parameter": parameter}`, returning the
+/// unwrapped result
+///
+/// With the `tracing` feature, the call is recorded in a span along with
+/// Coral's status and correlation ID.
+pub(crate) async fn call<P: Serialize, T: DeserializeOwned>(
+    path: &'static str,
+    token: Option<&str>,
+    parameter: P,
+    client: &NsoClient,
+) -> Result<T, NsoError> {
+    #[derive(Serialize)]
+    struct Body<P> {
+        parameter: P,
+    }
+    let call = async {
+        let request = request(path, token, client).json(&Body { parameter });
+        result(send(path, request, client).await?).await
+    };
+    #[cfg(feature = "tracing")]
+    let call = tracing::Instrument::instrument(
+        call,
+        tracing::info_span!(
+            "coral",
+            path,
+            coral_status = tracing::field::Empty,
+            correlation_id = tracing::field::Empty,
+        ),
+    );
+    call.await
+}
+
+/// Unwrap the `{status, result}` envelope used by every Coral response
+///
+/// A successful response without a result is unwrapped as if its result were
+/// `null`, so endpoints which return nothing can be called with a `T` of
+/// [`IgnoredAny`](serde::de::IgnoredAny).
+pub(crate) async fn result<T: DeserializeOwned>(
+    response: Response,
+) -> Result<T, NsoError> {
+    #[derive(Deserialize)]
+    #[allow(non_snake_case)]
+    struct Envelope<T> {
+        status: u32,
+        result: Option<T>,
+        errorMessage: Option<String>,
+        correlationId: Option<String>,
+    }
+    let envelope: Envelope<T> = json(response).await?;
+    #[cfg(feature = "tracing")]
+    {
+        let span = tracing::Span::current();
+        span.record("coral_status", envelope.status);
+        if let Some(correlation_id) = &envelope.correlationId {
+            span.record("correlation_id", correlation_id.as_str());
+        }
+    }
+    match envelope.result {
+        Some(result) if envelope.status == 0 => Ok(result),
+        // Mutations succeed with a `null` (or missing) result
+        None if envelope.status == 0 => {
+            T::deserialize(Value::Null).map_err(|source| NsoError::Deserialize {
+                source,
+                body: "null".to_string(),
+            })
+        },
+        _ => Err(NsoError::Coral(CoralError {
+            code: CoralErrorCode::from_status(envelope.status),
+            message: envelope.errorMessage.unwrap_or_default(),
+            correlation_id: envelope.correlationId,
+        })),
+    }
+}
@@ -0,0 +1,162 @@
+//! The user's own Coral profile, and who can see their presence
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+
+use super::{FriendCode, Presence};
+use crate::{coral, CoralToken, NsoClient, NsoError};
+
+/// The user's own profile, as returned by [`get_current_user`]
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CurrentUser {
+    /// The user's Coral user ID
+    pub id: u64,
+    /// The user's Nintendo Switch account ID
+    pub nsa_id: String,
+    pub name: String,
+    pub image_uri: String,
+    pub links: UserLinks,
+    /// The user's own presence, as their friends see it
+    pub presence: Presence,
+}
+
+impl CurrentUser {
+    /// The user's friend code
+    #[must_use]
+    pub fn friend_code(&self) -> FriendCode {
+        self.links.friend_code.id
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserLinks {
+    pub friend_code: FriendCodeLink,
+}
+
+/// The user's friend code, and whether it can be changed
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FriendCodeLink {
+    pub id: FriendCode,
+    /// Whether a new friend code can be generated now
+    pub regenerable: bool,
+    /// When a new friend code can next be generated, in seconds since the
+    /// Unix epoch
+    pub regenerable_at: u64,
+}
+
+/// Get the user's own profile
+///
+/// # Errors
+///
+/// If the request to Nintendo fails, or if Coral responds with an error (for
+/// example, if the login token has expired)
+pub async fn get_current_user(
+    login_token: &CoralToken,
+    client: &NsoClient,
+) -> Result<CurrentUser, NsoError> {
+    #[derive(Serialize)]
+    struct Parameter {}
+    coral::call(
+        "/v3/User/ShowSelf",
+        Some(login_token.expose()),
+        Parameter {},
+        client,
+    )
+    .await
+}
+
+/// Who can see the user's presence
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum PresencePermission {
+    /// Every friend
+    Friends,
+    /// Only best friends
+    #[serde(rename = "FAVORITE_FRIENDS")]
+    FavouriteFriends,
+    /// Nobody
+    #[serde(rename = "SELF")]
+    Nobody,
+}
+
+/// The user's permissions, as returned by [`get_permissions`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UserPermissions {
+    /// Identifies this version of the permissions, which must be sent back
+    /// to update them (see [`update_presence_permission`])
+    pub etag: String,
+    pub presence: PresencePermission,
+}
+
+/// Get who can see the user's presence, and the etag needed to change it
+///
+/// # Errors
+///
+/// If the request to Nintendo fails, or if Coral responds with an error (for
+/// example, if the login token has expired)
+pub async fn get_permissions(
+    login_token: &CoralToken,
+    client: &NsoClient,
+) -> Result<UserPermissions, NsoError> {
+    #[derive(Serialize)]
+    struct Parameter {}
+    #[derive(Deserialize)]
+    struct Result {
+        etag: String,
+        permissions: Permissions,
+    }
+    #[derive(Deserialize)]
+    struct Permissions {
+        presence: PresencePermission,
+    }
+    let result: Result = coral::call(
+        "/v3/User/Permissions/ShowSelf",
+        Some(login_token.expose()),
+        Parameter {},
+        client,
+    )
+    .await?;
+    Ok(UserPermissions {
+        etag: result.etag,
+        presence: result.permissions.presence,
+    })
+}
+
+/// Change who can see the user's presence
+///
+/// `etag` must be the etag of the user's current permissions, from
+/// [`get_permissions`]; Coral rejects the change if they have changed since.
+///
+/// # Errors
+///
+/// If the request to Nintendo fails, or if Coral responds with an error (for
+/// example, if `etag` is out of date)
+pub async fn update_presence_permission(
+    presence: PresencePermission,
+    etag: &str,
+    login_token: &CoralToken,
+    client: &NsoClient,
+) -> Result<(), NsoError> {
+    #[derive(Serialize)]
+    struct Parameter<'a> {
+        permissions: Permissions,
+        etag: &'a str,
+    }
+    #[derive(Serialize)]
+    struct Permissions {
+        presence: PresencePermission,
+    }
+    coral::call::<_, IgnoredAny>(
+        "/v3/User/Permissions/UpdateSelf",
+        Some(login_token.expose()),
+        Parameter {
+            permissions: Permissions { presence },
+            etag,
+        },
+        client,
+    )
+    .await?;
+    Ok(())
+}
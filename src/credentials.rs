@@ -0,0 +1,336 @@
+//! Persisting tokens between runs, so the user doesn't have to log in every
+//! time
+//!
+//! Credentials are stored as JSON, or as TOML if the file name ends in
+//! `.toml`. Each file records its format version, so that files written by
+//! older versions of the crate can be migrated when loaded.
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
+
+use crate::{JwtClaims, NsoError};
+
+/// The version of the credential format written by this version of the crate
+pub const CREDENTIALS_VERSION: u32 = 1;
+
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// A token, along with when it was obtained and when it expires
+///
+/// Like the types in [`secrets`](crate::secrets), the token is redacted from
+/// `Debug` output, and zeroed in memory when dropped.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredToken {
+    pub token: String,
+    /// In seconds since the Unix epoch
+    pub obtained_at: u64,
+    /// In seconds since the Unix epoch, or `None` if unknown
+    pub expires_at: Option<u64>,
+}
+
+impl fmt::Debug for StoredToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StoredToken")
+            .field("token", &format_args!("<redacted>"))
+            .field("obtained_at", &self.obtained_at)
+            .field("expires_at", &self.expires_at)
+            .finish()
+    }
+}
+
+impl Zeroize for StoredToken {
+    fn zeroize(&mut self) {
+        self.token.zeroize();
+    }
+}
+
+impl Drop for StoredToken {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+impl ZeroizeOnDrop for StoredToken {}
+
+impl StoredToken {
+    /// Store `token`, obtained just now and valid for `expires_in`
+    #[must_use]
+    pub fn new(token: impl Into<String>, expires_in: Option<Duration>) -> Self {
+        let now = SystemTime::now();
+        Self {
+            token: token.into(),
+            obtained_at: unix_seconds(now),
+            expires_at: expires_in.map(|expires_in| unix_seconds(now + expires_in)),
+        }
+    }
+
+    #[must_use]
+    pub fn obtained_at(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.obtained_at)
+    }
+
+    #[must_use]
+    pub fn expires_at(&self) -> Option<SystemTime> {
+        self.expires_at
+            .map(|expires_at| UNIX_EPOCH + Duration::from_secs(expires_at))
+    }
+
+    /// Whether the token is known to still be valid `margin` from now
+    #[must_use]
+    pub fn is_fresh(&self, margin: Duration) -> bool {
+        self.expires_at()
+            .is_some_and(|expires_at| SystemTime::now() + margin < expires_at)
+    }
+}
+
+/// Every token obtained for a Nintendo Account
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Credentials {
+    /// The format version; always [`CREDENTIALS_VERSION`] once loaded
+    pub version: u32,
+    pub session_token: Option<StoredToken>,
+    pub access_token: Option<StoredToken>,
+    pub id_token: Option<StoredToken>,
+    /// The Coral login token
+    pub login_token: Option<StoredToken>,
+    /// Game web tokens, keyed by game ID
+    #[serde(default)]
+    pub web_tokens: BTreeMap<String, StoredToken>,
+    /// The Splatoon 3 `bullet_token`
+    pub bullet_token: Option<StoredToken>,
+    /// The Splatoon 2 `iksm_session`
+    pub iksm_session: Option<StoredToken>,
+}
+
+impl Default for Credentials {
+    fn default() -> Self {
+        Self {
+            version: CREDENTIALS_VERSION,
+            session_token: None,
+            access_token: None,
+            id_token: None,
+            login_token: None,
+            web_tokens: BTreeMap::new(),
+            bullet_token: None,
+            iksm_session: None,
+        }
+    }
+}
+
+impl Credentials {
+    /// Credentials holding only a `session_token`, with its expiry taken from
+    /// its claims
+    #[must_use]
+    pub fn from_session_token(session_token: impl Into<String>) -> Self {
+        Self {
+            session_token: Some(stored_jwt(session_token.into())),
+            ..Self::default()
+        }
+    }
+
+    #[must_use]
+    pub fn web_token(&self, game_id: u64) -> Option<&StoredToken> {
+        self.web_tokens.get(&game_id.to_string())
+    }
+
+    pub fn set_web_token(&mut self, game_id: u64, token: StoredToken) {
+        self.web_tokens.insert(game_id.to_string(), token);
+    }
+
+    /// Take every token from `other` which expires later than the one held
+    /// here (or which isn't held here at all)
+    ///
+    /// The access and ID tokens are taken together, as they are issued
+    /// together. The `session_token` is only taken if none is held.
+    pub fn merge(&mut self, other: Credentials) {
+        fn newer(ours: &Option<StoredToken>, theirs: &Option<StoredToken>) -> bool {
+            match (ours, theirs) {
+                (_, None) => false,
+                (None, Some(_)) => true,
+                (Some(ours), Some(theirs)) => theirs.expires_at > ours.expires_at,
+            }
+        }
+        if self.session_token.is_none() {
+            self.session_token = other.session_token;
+        }
+        if newer(&self.access_token, &other.access_token) {
+            self.access_token = other.access_token;
+            self.id_token = other.id_token;
+        }
+        for (ours, theirs) in [
+            (&mut self.login_token, other.login_token),
+            (&mut self.bullet_token, other.bullet_token),
+            (&mut self.iksm_session, other.iksm_session),
+        ] {
+            if newer(ours, &theirs) {
+                *ours = theirs;
+            }
+        }
+        for (game_id, theirs) in other.web_tokens {
+            match self.web_tokens.get(&game_id) {
+                Some(ours) if ours.expires_at >= theirs.expires_at => {},
+                _ => {
+                    self.web_tokens.insert(game_id, theirs);
+                },
+            }
+        }
+    }
+
+    /// Parse credentials from JSON, migrating them if needed
+    ///
+    /// # Errors
+    ///
+    /// If the JSON is invalid, or was written by a newer version of the crate.
+    pub fn from_json(json: &str) -> Result<Self, NsoError> {
+        let value = serde_json::from_str(json)
+            .map_err(|err| NsoError::InvalidCredentials(err.to_string()))?;
+        Self::from_value(value)
+    }
+
+    /// Parse credentials from TOML, migrating them if needed
+    ///
+    /// # Errors
+    ///
+    /// If the TOML is invalid, or was written by a newer version of the crate.
+    pub fn from_toml(toml: &str) -> Result<Self, NsoError> {
+        let value = toml::from_str(toml)
+            .map_err(|err| NsoError::InvalidCredentials(err.to_string()))?;
+        Self::from_value(value)
+    }
+
+    /// # Errors
+    ///
+    /// Never, in practice; the error is kept for symmetry with
+    /// [`to_toml`](Self::to_toml).
+    pub fn to_json(&self) -> Result<String, NsoError> {
+        serde_json::to_string_pretty(self)
+            .map_err(|err| NsoError::InvalidCredentials(err.to_string()))
+    }
+
+    /// # Errors
+    ///
+    /// If the credentials cannot be represented as TOML.
+    pub fn to_toml(&self) -> Result<String, NsoError> {
+        toml::to_string_pretty(self)
+            .map_err(|err| NsoError::InvalidCredentials(err.to_string()))
+    }
+
+    /// Load credentials from `path`, as TOML if it ends in `.toml` and JSON
+    /// otherwise
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be read or parsed.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, NsoError> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)?;
+        if is_toml(path) {
+            Self::from_toml(&contents)
+        } else {
+            Self::from_json(&contents)
+        }
+    }
+
+    /// Save credentials to `path`, as TOML if it ends in `.toml` and JSON
+    /// otherwise
+    ///
+    /// The file is replaced atomically, so a crash mid-write can't corrupt it.
+    /// On Unix, it is only readable by the user.
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be written.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), NsoError> {
+        let path = path.as_ref();
+        let contents = Zeroizing::new(if is_toml(path) {
+            self.to_toml()?
+        } else {
+            self.to_json()?
+        });
+        write_private(path, contents.as_bytes())?;
+        Ok(())
+    }
+
+    /// Parse credentials from any self-describing format, migrating them if
+    /// needed
+    pub(crate) fn from_value(value: Value) -> Result<Self, NsoError> {
+        serde_json::from_value(migrate(value)?)
+            .map_err(|err| NsoError::InvalidCredentials(err.to_string()))
+    }
+}
+
+/// Replace the file at `path` with `contents` atomically, via a `.tmp` file
+/// next to it
+///
+/// On Unix, the file is created with mode `0600`, so that the secrets in it
+/// are only readable by the user.
+pub(crate) fn write_private(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    // A leftover temporary file may have been created with looser permissions
+    match fs::remove_file(&temp_path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+        _ => {},
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options.open(&temp_path)?;
+    file.write_all(contents)?;
+    // Flush the contents to disk before the rename, so that a crash can't
+    // leave `path` pointing at a partly-written file
+    file.sync_all()?;
+    fs::rename(&temp_path, path)
+}
+
+fn is_toml(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "toml")
+}
+
+/// Store a JWT, with its expiry taken from its claims
+fn stored_jwt(token: String) -> StoredToken {
+    let claims = JwtClaims::decode(&token).ok();
+    StoredToken {
+        obtained_at: claims
+            .as_ref()
+            .map_or_else(|| unix_seconds(SystemTime::now()), |claims| claims.iat),
+        expires_at: claims.map(|claims| claims.exp),
+        token,
+    }
+}
+
+/// Bring credentials written by any version of the crate up to
+/// [`CREDENTIALS_VERSION`]
+///
+/// Unversioned credentials were never written by this crate, so they are
+/// rejected as version 0.
+fn migrate(value: Value) -> Result<Value, NsoError> {
+    let version = match value.get("version") {
+        None => 0,
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| {
+                NsoError::InvalidCredentials("`version` is not an integer".to_string())
+            })?,
+    };
+    if !(1..=CREDENTIALS_VERSION).contains(&version) {
+        return Err(NsoError::UnsupportedCredentialsVersion(version));
+    }
+    Ok(value)
+}
@@ -0,0 +1,53 @@
+/// The base URLs of every host the crate talks to
+///
+/// Each URL has no trailing slash. The defaults are the real Nintendo and
+/// third-party hosts; override them to route requests through a gateway, or
+/// to a mock server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Endpoints {
+    /// Nintendo Accounts' OAuth endpoints
+    pub accounts: String,
+    /// The Nintendo Accounts user API
+    pub accounts_api: String,
+    /// Coral, the API behind the Nintendo Switch Online app
+    pub coral: String,
+    /// SplatNet 2
+    pub splatoon2: String,
+    /// SplatNet 3
+    pub splatoon3: String,
+    /// imink's f-token API
+    pub imink: String,
+    /// The public nxapi-znca-api deployment
+    pub nxapi_znca_api: String,
+}
+
+impl Endpoints {
+    /// Point every endpoint at the same host (for example, a mock server)
+    #[must_use]
+    pub fn all_at(base: &str) -> Self {
+        let base = base.trim_end_matches('/');
+        Self {
+            accounts: base.to_string(),
+            accounts_api: base.to_string(),
+            coral: base.to_string(),
+            splatoon2: base.to_string(),
+            splatoon3: base.to_string(),
+            imink: base.to_string(),
+            nxapi_znca_api: base.to_string(),
+        }
+    }
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Self {
+            accounts: "https://accounts.nintendo.com".to_string(),
+            accounts_api: "https://api.accounts.nintendo.com".to_string(),
+            coral: "https://api-lp1.znc.srv.nintendo.net".to_string(),
+            splatoon2: "https://app.splatoon2.nintendo.net".to_string(),
+            splatoon3: "https://api.lp1.av5ja.srv.nintendo.net".to_string(),
+            imink: "https://api.imink.app".to_string(),
+            nxapi_znca_api: "https://nxapi-znca-api.fancy.org.uk".to_string(),
+        }
+    }
+}
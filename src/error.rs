@@ -0,0 +1,198 @@
+use std::fmt;
+use std::time::Duration;
+
+use reqwest::StatusCode;
+
+use crate::coral::CoralError;
+
+/// An error produced while talking to Nintendo or an f-token provider
+#[derive(Debug, thiserror::Error)]
+pub enum NsoError {
+    /// The request could not be sent, or the response could not be read
+    /// (for example, if the connection fails)
+    #[error("request failed: {0}")]
+    Request(#[from] reqwest::Error),
+    /// The server responded with an unsuccessful HTTP status
+    #[error("{url} responded with HTTP {status}")]
+    Status {
+        url: String,
+        status: StatusCode,
+        body: String,
+        /// How long the server's `Retry-After` header asked to wait, if it
+        /// sent one
+        retry_after: Option<Duration>,
+    },
+    /// Nintendo Accounts rejected the request (for example, if the
+    /// `session_token` has been revoked)
+    #[error("Nintendo Accounts error `{error}`: {description}")]
+    Accounts { error: String, description: String },
+    /// Coral (the Nintendo Switch Online app API) responded with an error
+    /// payload instead of a result
+    #[error(transparent)]
+    Coral(#[from] CoralError),
+    /// The f-token provider failed to generate an f-token
+    #[error("f-token provider error (HTTP {status}): {reason}")]
+    FToken {
+        status: StatusCode,
+        reason: String,
+        /// How long the provider's `Retry-After` header asked to wait, if it
+        /// sent one
+        retry_after: Option<Duration>,
+    },
+    /// Every f-token provider in a
+    /// [`FailoverFTokenProvider`](crate::f_token::FailoverFTokenProvider)
+    /// failed
+    #[error("every f-token provider failed: {}", FTokenFailures(.0))]
+    FTokenProvidersExhausted(Vec<FTokenFailure>),
+    /// A [`FailoverFTokenProvider`](crate::f_token::FailoverFTokenProvider)
+    /// was used without adding any providers to it
+    #[error("no f-token providers were configured")]
+    NoFTokenProviders,
+    /// The 'Select this person' URL is not a login callback
+    #[error("not a login callback URL")]
+    InvalidCallbackUrl,
+    /// Nintendo Accounts redirected with an error instead of a
+    /// `session_token_code` (for example, `access_denied` if the user
+    /// cancelled the login)
+    #[error("login failed with `{error}`")]
+    AuthorizationDenied {
+        error: String,
+        description: Option<String>,
+    },
+    /// The login callback's `state` does not match the login request's, so it
+    /// may have been forged
+    #[error("login callback state does not match the login request")]
+    StateMismatch,
+    /// A token which should be a JWT could not be decoded
+    #[error("malformed JWT: {0}")]
+    InvalidJwt(String),
+    /// The `session_token` has expired, so the user must log in again
+    #[error("the session_token has expired")]
+    SessionTokenExpired,
+    /// The request took longer than the allowed time
+    #[error("request timed out after {0:?}")]
+    Timeout(Duration),
+    /// The response did not set a cookie that it should have
+    #[error("response did not set the `{0}` cookie")]
+    MissingCookie(&'static str),
+    /// The response body did not have the expected shape
+    #[error("failed to deserialise response: {source}")]
+    Deserialize {
+        source: serde_json::Error,
+        body: String,
+    },
+    /// A file could not be read or written
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    /// Stored [`Credentials`](crate::credentials::Credentials) could not be
+    /// parsed or serialised
+    #[error("invalid credentials: {0}")]
+    InvalidCredentials(String),
+    /// Stored credentials were written by a newer version of this crate, or
+    /// are unversioned
+    #[error("unsupported credentials version {0}")]
+    UnsupportedCredentialsVersion(u32),
+    /// An encrypted credential vault could not be decrypted, because the
+    /// passphrase is wrong or the ciphertext has been tampered with
+    #[error("incorrect passphrase, or the vault's ciphertext was tampered with")]
+    IncorrectPassphrase,
+    /// A credential vault file is malformed, or asks for key derivation
+    /// parameters outside the limits in `KdfParams`
+    #[error("invalid vault: {0}")]
+    InvalidVault(String),
+    /// A vault was written by a newer version of this crate
+    #[error("unsupported vault version {0}")]
+    UnsupportedVaultVersion(u32),
+    /// A saved [`AccountRegistry`](crate::accounts::AccountRegistry) could
+    /// not be parsed or serialised
+    #[error("invalid account registry: {0}")]
+    InvalidRegistry(String),
+    /// A saved account registry was written by a newer version of this crate
+    #[error("unsupported account registry version {0}")]
+    UnsupportedRegistryVersion(u32),
+    /// No account in an [`AccountRegistry`](crate::accounts::AccountRegistry)
+    /// has this ID or nickname
+    #[error("no account with the ID or nickname `{0}`")]
+    UnknownAccount(String),
+    /// More than one account in an
+    /// [`AccountRegistry`](crate::accounts::AccountRegistry) has this nickname
+    #[error("more than one account has the nickname `{0}`")]
+    AmbiguousAccount(String),
+    /// The SplatNet 3 web view was downloaded, but its version couldn't be
+    /// found in it
+    #[error("could not find the SplatNet 3 web view version: {0}")]
+    WebViewVersion(String),
+    /// A string is not a friend code of the form `SW-1234-5678-9012`
+    #[error("invalid friend code: {0}")]
+    InvalidFriendCode(String),
+    /// A SplatNet 3 query was given by operation name, but no hash is known
+    /// for it
+    #[error("no persisted query named {0}")]
+    UnknownQuery(String),
+    /// A persisted query registry could not be parsed
+    #[error("invalid persisted query registry: {0}")]
+    InvalidQueryRegistry(String),
+    /// SplatNet 3 no longer recognises the hash sent for a persisted query,
+    /// usually because the web view has been updated
+    #[error("persisted query {operation} ({hash}) is stale")]
+    StaleQuery { operation: String, hash: String },
+    /// A [`Cassette`](crate::cassette::Cassette) could not be parsed, or holds
+    /// a response which cannot be replayed
+    #[error("invalid cassette: {0}")]
+    InvalidCassette(String),
+    /// A client replaying a cassette was asked to send a request which
+    /// wasn't recorded (or whose recorded response has already been served)
+    #[error("no recorded response for {method} {url}")]
+    ReplayMiss { method: String, url: String },
+}
+
+impl NsoError {
+    /// Whether the error is likely temporary, so the request may succeed if
+    /// retried (for example, a timeout, an HTTP 5xx, or HTTP 429)
+    #[must_use]
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Request(err) => err.is_timeout() || err.is_connect(),
+            Self::Status { status, .. } | Self::FToken { status, .. } => {
+                status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS
+            },
+            Self::Coral(err) => err.code.is_transient(),
+            Self::Timeout(_) => true,
+            _ => false,
+        }
+    }
+
+    /// How long the server asked to wait before retrying, if it did
+    #[must_use]
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::Status { retry_after, .. } | Self::FToken { retry_after, .. } => {
+                *retry_after
+            },
+            _ => None,
+        }
+    }
+}
+
+/// The error returned by one f-token provider
+#[derive(Debug)]
+pub struct FTokenFailure {
+    /// The provider's [`name`](crate::FTokenProvider::name)
+    pub provider: String,
+    /// The last error it returned
+    pub error: NsoError,
+}
+
+struct FTokenFailures<'a>(&'a [FTokenFailure]);
+
+impl fmt::Display for FTokenFailures<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, failure) in self.0.iter().enumerate() {
+            if i != 0 {
+                f.write_str("; ")?;
+            }
+            write!(f, "{}: {}", failure.provider, failure.error)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,379 @@
+//! Providers of the f-tokens needed to log in to Coral
+//!
+//! f-tokens are generated by the NSO app's native code, so they have to be
+//! requested from a third-party service which runs it.
+// TODO: Reverse-engineer libvoip so we don't depend on a third party
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use reqwest::header::USER_AGENT;
+use serde::{Deserialize, Serialize};
+
+use crate::error::FTokenFailure;
+use crate::transport::{f_token_json, send};
+use crate::{NsoClient, NsoError, NSO_VERSION, RUST_NSO_USER_AGENT};
+
+/// Which token an f-token is being generated for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashMethod {
+    /// The f-token sent to `Account/Login`, generated from the `id_token`
+    Nso = 1,
+    /// The f-token sent to `Game/GetWebServiceToken`, generated from the
+    /// login token
+    App = 2,
+}
+
+/// An f-token, as returned by a provider
+#[derive(Clone, Deserialize)]
+pub struct FToken {
+    pub f: String,
+    pub timestamp: i64,
+    pub request_id: String,
+}
+
+/// A service which can generate f-tokens
+#[async_trait]
+pub trait FTokenProvider: Send + Sync {
+    /// A short, human-readable name for the provider
+    fn name(&self) -> &str;
+
+    /// Generate an f-token for `token` (either the `id_token` or the login
+    /// token, depending on `hash_method`)
+    ///
+    /// # Errors
+    ///
+    /// If the request to the provider fails, or if the provider cannot
+    /// generate an f-token.
+    async fn get_f(
+        &self,
+        hash_method: HashMethod,
+        token: &str,
+        client: &NsoClient,
+    ) -> Result<FToken, NsoError>;
+}
+
+#[async_trait]
+impl<P: FTokenProvider + ?Sized> FTokenProvider for Arc<P> {
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+
+    async fn get_f(
+        &self,
+        hash_method: HashMethod,
+        token: &str,
+        client: &NsoClient,
+    ) -> Result<FToken, NsoError> {
+        (**self).get_f(hash_method, token, client).await
+    }
+}
+
+/// Request an f-token from an API speaking imink's protocol
+async fn imink_compatible_f(
+    url: &str,
+    hash_method: HashMethod,
+    token: &str,
+    client: &NsoClient,
+) -> Result<FToken, NsoError> {
+    #[derive(Serialize)]
+    struct Body<'a> {
+        hash_method: String,
+        token: &'a str,
+    }
+    let request = client
+        .post(url)
+        .header(USER_AGENT, RUST_NSO_USER_AGENT)
+        .json(&Body {
+            hash_method: (hash_method as u8).to_string(),
+            token,
+        });
+    let response = send("f_token", request, client).await?;
+    f_token_json(response).await
+}
+
+/// The [imink f API](https://github.com/imink-app/f-API)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Imink;
+
+#[async_trait]
+impl FTokenProvider for Imink {
+    fn name(&self) -> &str {
+        "imink"
+    }
+
+    async fn get_f(
+        &self,
+        hash_method: HashMethod,
+        token: &str,
+        client: &NsoClient,
+    ) -> Result<FToken, NsoError> {
+        let url = format!("{}/f", client.endpoints().imink);
+        imink_compatible_f(&url, hash_method, token, client).await
+    }
+}
+
+/// A deployment of [nxapi-znca-api](https://github.com/samuelthomas2774/nxapi-znca-api)
+///
+/// The default is the public deployment in
+/// [`Endpoints::nxapi_znca_api`](crate::Endpoints::nxapi_znca_api).
+#[derive(Debug, Clone, Default)]
+pub struct NxapiZncaApi {
+    base_url: Option<String>,
+}
+
+impl NxapiZncaApi {
+    /// Use the deployment at `base_url` (the URL its `/api/znca/f` endpoint
+    /// is under)
+    #[must_use]
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            base_url: Some(base_url.into()),
+        }
+    }
+}
+
+#[async_trait]
+impl FTokenProvider for NxapiZncaApi {
+    fn name(&self) -> &str {
+        "nxapi-znca-api"
+    }
+
+    async fn get_f(
+        &self,
+        hash_method: HashMethod,
+        token: &str,
+        client: &NsoClient,
+    ) -> Result<FToken, NsoError> {
+        #[derive(Serialize)]
+        struct Body<'a> {
+            hash_method: u8,
+            token: &'a str,
+        }
+        let base_url = self
+            .base_url
+            .as_deref()
+            .unwrap_or(&client.endpoints().nxapi_znca_api);
+        let request = client
+            .post(format!("{base_url}/api/znca/f"))
+            .header(USER_AGENT, RUST_NSO_USER_AGENT)
+            .header("X-znca-Platform", "Android")
+            .header("X-znca-Version", NSO_VERSION)
+            .json(&Body {
+                hash_method: hash_method as u8,
+                token,
+            });
+        let response = send("f_token", request, client).await?;
+        f_token_json(response).await
+    }
+}
+
+/// Any other f-token API which speaks imink's protocol
+#[derive(Debug, Clone)]
+pub struct CustomFTokenProvider {
+    name: String,
+    url: String,
+}
+
+impl CustomFTokenProvider {
+    /// Use the API at `url`, calling it `name` in error messages
+    #[must_use]
+    pub fn new(name: impl Into<String>, url: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            url: url.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl FTokenProvider for CustomFTokenProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn get_f(
+        &self,
+        hash_method: HashMethod,
+        token: &str,
+        client: &NsoClient,
+    ) -> Result<FToken, NsoError> {
+        imink_compatible_f(&self.url, hash_method, token, client).await
+    }
+}
+
+/// A provider which never makes a request, returning the same f-tokens every
+/// time
+///
+/// This is meant for tests, where the server checking the f-tokens is a fake
+/// too (such as the `mock` feature's `MockServer`).
+#[derive(Clone)]
+pub struct StaticFTokenProvider {
+    nso: FToken,
+    app: FToken,
+}
+
+impl StaticFTokenProvider {
+    /// Return `nso` for [`HashMethod::Nso`] and `app` for [`HashMethod::App`]
+    #[must_use]
+    pub fn new(nso: FToken, app: FToken) -> Self {
+        Self { nso, app }
+    }
+}
+
+#[async_trait]
+impl FTokenProvider for StaticFTokenProvider {
+    fn name(&self) -> &str {
+        "static"
+    }
+
+    async fn get_f(
+        &self,
+        hash_method: HashMethod,
+        _token: &str,
+        _client: &NsoClient,
+    ) -> Result<FToken, NsoError> {
+        Ok(match hash_method {
+            HashMethod::Nso => self.nso.clone(),
+            HashMethod::App => self.app.clone(),
+        })
+    }
+}
+
+/// How often, and how patiently, to retry a provider which fails with a
+/// retryable error (see [`NsoError::is_retryable`])
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// How many times to retry each provider before moving on to the next
+    pub max_retries: u32,
+    /// How long to wait before the first retry; each subsequent wait is twice
+    /// as long
+    ///
+    /// If the provider sends a longer `Retry-After`, that is waited instead.
+    pub initial_backoff: Duration,
+    /// The longest to wait between retries, even if the provider's
+    /// `Retry-After` asks for longer
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 2,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(8),
+        }
+    }
+}
+
+/// An ordered list of providers, each tried in turn until one succeeds
+///
+/// If every provider fails, the error lists what each of them returned. If
+/// the list is empty, [`NsoError::NoFTokenProviders`] is returned.
+pub struct FailoverFTokenProvider {
+    providers: Vec<(Box<dyn FTokenProvider>, Duration)>,
+    retry_policy: RetryPolicy,
+}
+
+impl FailoverFTokenProvider {
+    /// Create an empty provider list, using the default [`RetryPolicy`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            providers: Vec::new(),
+            retry_policy: RetryPolicy::default(),
+        }
+    }
+
+    /// Add `provider` to the end of the list, giving up on each request to it
+    /// after `timeout`
+    #[must_use]
+    pub fn with_provider(
+        mut self,
+        provider: impl FTokenProvider + 'static,
+        timeout: Duration,
+    ) -> Self {
+        self.providers.push((Box::new(provider), timeout));
+        self
+    }
+
+    #[must_use]
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    async fn try_provider(
+        &self,
+        provider: &dyn FTokenProvider,
+        timeout: Duration,
+        hash_method: HashMethod,
+        token: &str,
+        client: &NsoClient,
+    ) -> Result<FToken, NsoError> {
+        let mut backoff = self.retry_policy.initial_backoff;
+        let mut retries = 0;
+        loop {
+            let err = match tokio::time::timeout(
+                timeout,
+                provider.get_f(hash_method, token, client),
+            )
+            .await
+            {
+                Ok(Ok(f)) => return Ok(f),
+                Ok(Err(err)) => err,
+                Err(_) => NsoError::Timeout(timeout),
+            };
+            if retries == self.retry_policy.max_retries || !err.is_retryable() {
+                return Err(err);
+            }
+            // Respect the provider's Retry-After, if it is longer
+            let delay = err.retry_after().map_or(backoff, |retry_after| {
+                retry_after.max(backoff).min(self.retry_policy.max_backoff)
+            });
+            tokio::time::sleep(delay).await;
+            backoff = (backoff * 2).min(self.retry_policy.max_backoff);
+            retries += 1;
+        }
+    }
+}
+
+impl Default for FailoverFTokenProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl FTokenProvider for FailoverFTokenProvider {
+    fn name(&self) -> &str {
+        "failover"
+    }
+
+    async fn get_f(
+        &self,
+        hash_method: HashMethod,
+        token: &str,
+        client: &NsoClient,
+    ) -> Result<FToken, NsoError> {
+        if self.providers.is_empty() {
+            return Err(NsoError::NoFTokenProviders);
+        }
+        let mut failures = Vec::with_capacity(self.providers.len());
+        for (provider, timeout) in &self.providers {
+            match self
+                .try_provider(&**provider, *timeout, hash_method, token, client)
+                .await
+            {
+                Ok(f) => return Ok(f),
+                Err(error) => {
+                    failures.push(FTokenFailure {
+                        provider: provider.name().to_string(),
+                        error,
+                    });
+                },
+            }
+        }
+        Err(NsoError::FTokenProvidersExhausted(failures))
+    }
+}
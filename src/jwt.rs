@@ -0,0 +1,82 @@
+//! Decoding the claims of the JWTs issued by Nintendo Accounts
+//!
+//! Signatures are not verified; the claims are only used to tell who a token
+//! belongs to and when it expires.
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::URL_SAFE_NO_PAD;
+use serde::{Deserialize, Deserializer};
+
+use crate::NsoError;
+
+/// The claims of a `session_token` or `id_token`
+#[derive(Clone, Debug, Deserialize)]
+pub struct JwtClaims {
+    /// The Nintendo Account ID
+    pub sub: String,
+    pub iss: String,
+    /// The client IDs the token was issued to
+    #[serde(deserialize_with = "one_or_many")]
+    pub aud: Vec<String>,
+    /// When the token was issued, in seconds since the Unix epoch
+    pub iat: u64,
+    /// When the token expires, in seconds since the Unix epoch
+    pub exp: u64,
+    /// The kind of token (`session_token` or `id_token`)
+    pub typ: Option<String>,
+    pub jti: Option<String>,
+}
+
+fn one_or_many<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(aud) => vec![aud],
+        OneOrMany::Many(aud) => aud,
+    })
+}
+
+impl JwtClaims {
+    /// Decode the claims of `token`, without verifying its signature
+    ///
+    /// # Errors
+    ///
+    /// If `token` is not a JWT, or its claims are missing a required field.
+    pub fn decode(token: &str) -> Result<Self, NsoError> {
+        let payload = token
+            .split('.')
+            .nth(1)
+            .ok_or_else(|| NsoError::InvalidJwt("missing payload".to_string()))?;
+        let payload = base64::decode_config(payload, URL_SAFE_NO_PAD)
+            .map_err(|err| NsoError::InvalidJwt(err.to_string()))?;
+        serde_json::from_slice(&payload)
+            .map_err(|err| NsoError::InvalidJwt(err.to_string()))
+    }
+
+    #[must_use]
+    pub fn issued_at(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.iat)
+    }
+
+    #[must_use]
+    pub fn expires_at(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.exp)
+    }
+
+    /// Whether the token has expired, or will within `margin`
+    #[must_use]
+    pub fn expires_within(&self, margin: Duration) -> bool {
+        SystemTime::now() + margin >= self.expires_at()
+    }
+
+    #[must_use]
+    pub fn is_expired(&self) -> bool {
+        self.expires_within(Duration::ZERO)
+    }
+}
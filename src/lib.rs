@@ -1,4 +1,32 @@
+pub mod accounts;
 pub mod apps;
+pub mod cache;
+pub mod cassette;
+mod client;
+pub mod coral;
+pub mod credentials;
+mod endpoints;
+pub mod error;
+pub mod f_token;
+pub mod jwt;
 pub mod login;
+#[cfg(feature = "mock")]
+pub mod mock;
+pub mod presence;
+pub mod secrets;
+pub mod session;
+mod transport;
+#[cfg(feature = "vault")]
+pub mod vault;
+pub use accounts::{Account, AccountRegistry};
 pub use apps::*;
+pub use cache::TokenCache;
+pub use client::NsoClient;
+pub use credentials::{Credentials, StoredToken};
+pub use endpoints::Endpoints;
+pub use error::NsoError;
+pub use f_token::FTokenProvider;
+pub use jwt::JwtClaims;
 pub use login::*;
+pub use secrets::*;
+pub use session::*;
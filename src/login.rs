@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::fmt;
 
 use base64::URL_SAFE;
 use const_format::formatcp;
@@ -8,16 +9,27 @@ use reqwest::header::{
     ACCEPT,
     ACCEPT_ENCODING,
     ACCEPT_LANGUAGE,
-    AUTHORIZATION,
     CONNECTION,
-    HOST,
     USER_AGENT,
 };
-use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use sha2::{Digest, Sha256};
 
+use crate::f_token::{FToken, FTokenProvider, HashMethod};
+use crate::transport::{accounts_json, send};
+use crate::{
+    coral,
+    AccessToken,
+    CoralToken,
+    Endpoints,
+    IdToken,
+    JwtClaims,
+    NsoClient,
+    NsoError,
+    SessionToken,
+};
+
 /// The version of Nintendo Switch Online that this library was built to mimic
 pub const NSO_VERSION: &str = "2.3.1";
 /// The user agent used to mock Nintendo Switch Online calls
@@ -37,59 +49,201 @@ pub const WEB_VIEW_USER_AGENT: &str = concat!(
 pub const RUST_NSO_USER_AGENT: &str =
     formatcp!("rust-nso/{}", env!("CARGO_PKG_VERSION"));
 
+/// The scopes requested by the NSO app
+pub const DEFAULT_SCOPES: [&str; 5] = [
+    "openid",
+    "user",
+    "user.birthday",
+    "user.mii",
+    "user.screenName",
+];
+/// The URL Nintendo Accounts redirects to once the user has logged in
+const REDIRECT_URI: &str = "npf71b963c1b7b6d119://auth";
+
 pub struct UrlAndVerifier {
     pub url: String,
     pub verifier: String,
 }
 
 /// Generates a login URL and verifier code.
+///
+/// This discards the OAuth `state`, and always logs in with the real Nintendo
+/// Accounts; prefer [`LoginRequest`], which checks the state and respects the
+/// client's [`Endpoints`].
 #[must_use]
 pub fn get_login_url_and_verifier() -> UrlAndVerifier {
-    let mut rng = ChaChaRng::from_entropy();
-    let auth_state = base64::encode_config(
-        {
-            let mut data = [0u8; 36];
-            rng.fill(&mut data[..32]);
-            rng.fill(&mut data[32..]);
-            data
-        },
-        URL_SAFE,
-    )
-    .trim_end_matches('=')
-    .to_string();
-
-    let auth_code_verifier = base64::encode_config(
-        {
-            let mut data = [0u8; 32];
-            rng.fill(&mut data);
-            data
-        },
-        URL_SAFE,
-    )
-    .trim_end_matches('=')
-    .to_string();
+    let LoginRequest { url, verifier, .. } =
+        LoginRequest::for_accounts(&Endpoints::default().accounts, DEFAULT_SCOPES);
+    UrlAndVerifier { url, verifier }
+}
 
-    let acv_hash = Sha256::digest(&auth_code_verifier);
-    let auth_code_challenge = base64::encode_config(acv_hash.as_slice(), URL_SAFE)
+fn random_base64<const N: usize>(rng: &mut ChaChaRng) -> String {
+    let mut data = [0u8; N];
+    rng.fill(&mut data[..]);
+    base64::encode_config(data, URL_SAFE)
         .trim_end_matches('=')
-        .to_string();
-
-    UrlAndVerifier {
-        url: format!(
-            concat!(
-                "https://accounts.nintendo.com/connect/1.0.0/authorize",
-                "?state={}",
-                "&redirect_uri=npf71b963c1b7b6d119://auth",
-                "&client_id=71b963c1b7b6d119",
-                "&scope=openid+user+user.birthday+user.mii+user.screenName",
-                "&response_type=session_token_code",
-                "&session_token_code_challenge={}",
-                "&session_token_code_challenge_method=S256",
-                "&theme=login_form",
+        .to_string()
+}
+
+/// A PKCE login in progress: the URL to send the user to, and the secrets
+/// needed to check the callback and redeem it for a `session_token`
+#[derive(Clone)]
+pub struct LoginRequest {
+    /// The URL the user should open to log in
+    pub url: String,
+    /// The OAuth `state`, which the callback must echo back
+    pub state: String,
+    /// The PKCE `session_token_code_verifier`
+    pub verifier: String,
+    pub scopes: Vec<String>,
+}
+
+impl fmt::Debug for LoginRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The URL carries the state too
+        let url = self.url.replace(&self.state, "<redacted>");
+        f.debug_struct("LoginRequest")
+            .field("url", &url)
+            .field("state", &format_args!("<redacted>"))
+            .field("verifier", &format_args!("<redacted>"))
+            .field("scopes", &self.scopes)
+            .finish()
+    }
+}
+
+impl LoginRequest {
+    /// Start a login requesting [`DEFAULT_SCOPES`] from `client`'s Nintendo
+    /// Accounts endpoint
+    #[must_use]
+    pub fn new(client: &NsoClient) -> Self {
+        Self::with_scopes(DEFAULT_SCOPES, client)
+    }
+
+    /// Start a login requesting `scopes` from `client`'s Nintendo Accounts
+    /// endpoint
+    #[must_use]
+    pub fn with_scopes<S: Into<String>>(
+        scopes: impl IntoIterator<Item = S>,
+        client: &NsoClient,
+    ) -> Self {
+        Self::for_accounts(&client.endpoints().accounts, scopes)
+    }
+
+    fn for_accounts<S: Into<String>>(
+        accounts: &str,
+        scopes: impl IntoIterator<Item = S>,
+    ) -> Self {
+        let mut rng = ChaChaRng::from_entropy();
+        let state = random_base64::<36>(&mut rng);
+        let verifier = random_base64::<32>(&mut rng);
+        let scopes: Vec<String> = scopes.into_iter().map(Into::into).collect();
+
+        let acv_hash = Sha256::digest(&verifier);
+        let auth_code_challenge = base64::encode_config(acv_hash.as_slice(), URL_SAFE)
+            .trim_end_matches('=')
+            .to_string();
+
+        Self {
+            url: format!(
+                concat!(
+                    "{}/connect/1.0.0/authorize",
+                    "?state={}",
+                    "&redirect_uri={}",
+                    "&client_id=71b963c1b7b6d119",
+                    "&scope={}",
+                    "&response_type=session_token_code",
+                    "&session_token_code_challenge={}",
+                    "&session_token_code_challenge_method=S256",
+                    "&theme=login_form",
+                ),
+                accounts,
+                state,
+                REDIRECT_URI,
+                scopes.join("+"),
+                auth_code_challenge,
             ),
-            auth_state, auth_code_challenge,
-        ),
-        verifier: auth_code_verifier,
+            state,
+            verifier,
+            scopes,
+        }
+    }
+
+    /// Check the user's 'Select this person' URL against this request,
+    /// returning its `session_token_code`
+    ///
+    /// # Errors
+    ///
+    /// If the URL is not a login callback, if the user denied the login, or
+    /// if its `state` does not match this request's.
+    pub fn verify_callback(&self, callback_url: &str) -> Result<String, NsoError> {
+        let callback =
+            LoginCallback::parse(callback_url).ok_or(NsoError::InvalidCallbackUrl)?;
+        if let Some(error) = callback.error {
+            return Err(NsoError::AuthorizationDenied {
+                error,
+                description: callback.error_description,
+            });
+        }
+        if callback.state.as_deref() != Some(&*self.state) {
+            return Err(NsoError::StateMismatch);
+        }
+        callback
+            .session_token_code
+            .ok_or(NsoError::InvalidCallbackUrl)
+    }
+
+    /// Check the user's 'Select this person' URL, and exchange its
+    /// `session_token_code` for a `session_token`
+    ///
+    /// # Errors
+    ///
+    /// If the callback is rejected by
+    /// [`verify_callback`](Self::verify_callback), or if
+    /// [`get_session_token`] fails.
+    pub async fn redeem(
+        &self,
+        callback_url: &str,
+        client: &NsoClient,
+    ) -> Result<SessionToken, NsoError> {
+        let session_token_code = self.verify_callback(callback_url)?;
+        get_session_token(&session_token_code, &self.verifier, client).await
+    }
+}
+
+/// The parameters Nintendo Accounts passes back in the fragment of the
+/// 'Select this person' URL
+#[derive(Clone, Debug, Default)]
+pub struct LoginCallback {
+    pub session_token_code: Option<String>,
+    pub state: Option<String>,
+    /// Set if the login failed (for example, to `access_denied` if the user
+    /// cancelled it)
+    pub error: Option<String>,
+    pub error_description: Option<String>,
+}
+
+impl LoginCallback {
+    /// Parse a 'Select this person' URL
+    ///
+    /// Returns `None` if the URL is not a login callback.
+    #[must_use]
+    pub fn parse(callback_url: &str) -> Option<Self> {
+        let fragment = callback_url
+            .trim()
+            .strip_prefix(REDIRECT_URI)?
+            .strip_prefix('#')?;
+        let mut callback = Self::default();
+        for (key, value) in url::form_urlencoded::parse(fragment.as_bytes()) {
+            let field = match &*key {
+                "session_token_code" => &mut callback.session_token_code,
+                "state" => &mut callback.state,
+                "error" => &mut callback.error,
+                "error_description" => &mut callback.error_description,
+                _ => continue,
+            };
+            *field = Some(value.into_owned());
+        }
+        Some(callback)
     }
 }
 
@@ -109,12 +263,13 @@ pub fn get_session_token_code_from_select_url(select_url: &str) -> Option<&str>
 ///
 /// # Errors
 ///
-/// This function will fail if the request to Nintendo's servers fails.
+/// This function will fail if the request to Nintendo's servers fails, or if
+/// Nintendo Accounts rejects the `session_token_code`.
 pub async fn get_session_token(
     session_token_code: &str,
     auth_code_verifier: &str,
-    client: &Client,
-) -> Result<String, reqwest::Error> {
+    client: &NsoClient,
+) -> Result<SessionToken, NsoError> {
     #[derive(Serialize)]
     struct Body<'a> {
         client_id: &'static str,
@@ -132,48 +287,58 @@ pub async fn get_session_token(
     }
     #[derive(Debug, Deserialize)]
     struct Resp {
-        session_token: String,
+        session_token: SessionToken,
         #[serde(flatten)]
         _rest: HashMap<String, Value>,
     }
-    Ok(client
-        .post("https://accounts.nintendo.com/connect/1.0.0/api/session_token")
+    let request = client
+        .post(format!(
+            "{}/connect/1.0.0/api/session_token",
+            client.endpoints().accounts,
+        ))
         .header(USER_AGENT, ONLINE_LOUNGE_USER_AGENT)
         .header(ACCEPT_LANGUAGE, "en-US")
         .header(ACCEPT, "application/json")
-        .header(HOST, "accounts.nintendo.com")
         .header(CONNECTION, "Keep-Alive")
         .header(ACCEPT_ENCODING, "gzip")
-        .form(&Body::new(session_token_code, auth_code_verifier))
-        .send()
-        .await?
-        .json::<Resp>()
-        .await?
-        .session_token)
+        .form(&Body::new(session_token_code, auth_code_verifier));
+    let response = send("accounts.session_token", request, client).await?;
+    Ok(accounts_json::<Resp>(response).await?.session_token)
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Tokens {
-    pub access_token: String,
-    pub id_token: String,
+    pub access_token: AccessToken,
+    pub id_token: IdToken,
     pub expires_in: u64,
     /// Should always be `"Bearer"`, but this isn't checked
     pub token_type: String,
-    /// Should always be
-    /// `["openid", "user", "user.birthday", "user.mii", "user.screenName"]`,
-    /// but this isn't checked
-    pub scope: [String; 5],
+    /// The scopes granted; [`DEFAULT_SCOPES`] unless others were requested
+    /// with [`LoginRequest::with_scopes`]
+    pub scope: Vec<String>,
+}
+
+impl Tokens {
+    /// Decode the claims of the `id_token`
+    ///
+    /// # Errors
+    ///
+    /// If the `id_token` is not a valid JWT.
+    pub fn id_token_claims(&self) -> Result<JwtClaims, NsoError> {
+        JwtClaims::decode(self.id_token.expose())
+    }
 }
 
 /// Get the access and ID tokens from Nintendo, based on a `session_token`
 ///
 /// # Errors
 ///
-/// This function will fail if the request to Nintendo's servers fails.
+/// This function will fail if the request to Nintendo's servers fails, or if
+/// Nintendo Accounts rejects the `session_token`.
 pub async fn get_access_token(
-    session_token: &str,
-    client: &Client,
-) -> Result<Tokens, reqwest::Error> {
+    session_token: &SessionToken,
+    client: &NsoClient,
+) -> Result<Tokens, NsoError> {
     #[derive(Serialize)]
     struct Body<'a> {
         client_id: &'static str,
@@ -190,118 +355,155 @@ pub async fn get_access_token(
         }
     }
 
-    client
-        .post("https://accounts.nintendo.com/connect/1.0.0/api/token")
+    let request = client
+        .post(format!(
+            "{}/connect/1.0.0/api/token",
+            client.endpoints().accounts,
+        ))
         .header(USER_AGENT, ONLINE_LOUNGE_USER_AGENT)
         .header(ACCEPT_LANGUAGE, "en-US")
         .header(ACCEPT, "application/json")
-        .header(HOST, "accounts.nintendo.com")
         .header(CONNECTION, "Keep-Alive")
         .header(ACCEPT_ENCODING, "gzip")
-        .json(&Body::new(session_token))
-        .send()
-        .await?
-        .json()
-        .await
+        .json(&Body::new(session_token.expose()));
+    let response = send("accounts.token", request, client).await?;
+    accounts_json(response).await
 }
 
-#[derive(Deserialize)]
+/// A Nintendo Account's profile, as returned by [`get_user_info`]
+///
+/// Timestamps are in seconds since the Unix epoch. Nintendo doesn't always
+/// send every field, so only those needed to log in are required.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct UserInfo {
+    /// The Nintendo Account ID
+    pub id: String,
+    pub nickname: String,
+    /// The account's partially-hidden email address or sign-in ID
+    pub screen_name: Option<String>,
     pub country: String,
+    /// In the form `YYYY-MM-DD`
     pub birthday: String,
     pub language: String,
-    #[serde(flatten)]
-    rest: HashMap<String, Value>,
-    // timezone: {id: String, name: String, utcOffset: String, utcOffsetSeconds: i64},
-    // clientFriendsOptedIn: bool,
-    // analyticsPermissions: {
-    //     internalAnalysis: {permitted: bool, updatedAt: u64},
-    //     targetMarketing: {permitted: bool, updatedAt: u64}
-    // },
-    // id: String,
-    // analyticsOptedIn: bool,
-    // isChild: bool,
-    // mii: {
-    //     clientId: String,
-    //     coreData: {"4": String},
-    //     etag: String,
-    //     favoriteColor: String,
-    //     id: String,
-    //     imageOrigin: String,
-    //     imageUriTemplate: String,
-    //     storeData: {"3": String},
-    //     type: String,
-    //     updatedAt: u64,
-    // },
-    // eachEmailOptedIn: {
-    //     deals: {optedIn: bool, updatedAt: u64},
-    //     survey: {optedIn: bool, updatedAt: u64}
-    // },
-    // screenName: String,
-    // analyticsOptedInUpdatedAt: u64,
-    // emailOptedIn: bool,
-    // createdAt: u64
-    // emailVerified: bool,
-    // region: (no idea, mine was null)
-    // clientFriendsOptedInUpdatedAt: u64,
-    // gender: String,
-    // candidateMiis: Vec<(no idea, mine was empty)>,
-    // emailOptedInUpdatedAt: u64,
-    // nickname: String,
-    // updatedAt: u64
+    pub gender: Option<String>,
+    pub region: Option<String>,
+    pub timezone: Option<Timezone>,
+    /// The account's Mii, if it has one
+    pub mii: Option<Mii>,
+    #[serde(default)]
+    pub candidate_miis: Vec<Mii>,
+    pub is_child: Option<bool>,
+    pub analytics_opted_in: Option<bool>,
+    pub analytics_opted_in_updated_at: Option<u64>,
+    pub analytics_permissions: Option<AnalyticsPermissions>,
+    pub client_friends_opted_in: Option<bool>,
+    pub client_friends_opted_in_updated_at: Option<u64>,
+    pub email_opted_in: Option<bool>,
+    pub email_opted_in_updated_at: Option<u64>,
+    pub each_email_opted_in: Option<EachEmailOptedIn>,
+    pub email_verified: Option<bool>,
+    pub created_at: Option<u64>,
+    pub updated_at: Option<u64>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Timezone {
+    /// The IANA name, such as `Europe/London`
+    pub id: String,
+    pub name: String,
+    /// In the form `+HH:MM`
+    pub utc_offset: String,
+    pub utc_offset_seconds: i64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Mii {
+    pub id: String,
+    pub client_id: String,
+    pub etag: String,
+    pub favorite_color: String,
+    /// The URL of the Mii's default image
+    pub image_origin: String,
+    /// A template for URLs of other renders of the Mii
+    pub image_uri_template: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// The Mii's data, keyed by format version
+    pub core_data: HashMap<String, String>,
+    /// The Mii's data as stored on the console, keyed by format version
+    pub store_data: HashMap<String, String>,
+    pub updated_at: u64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnalyticsPermissions {
+    pub internal_analysis: AnalyticsPermission,
+    pub target_marketing: AnalyticsPermission,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnalyticsPermission {
+    pub permitted: bool,
+    pub updated_at: u64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EachEmailOptedIn {
+    pub deals: EmailOptIn,
+    pub survey: EmailOptIn,
 }
 
-/// Get a user's country, birthday, and language
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmailOptIn {
+    pub opted_in: bool,
+    pub updated_at: u64,
+}
+
+/// Get a user's Nintendo Account profile
 ///
 /// # Errors
 ///
-/// This function will fail if the request to Nintendo's servers fails.
+/// This function will fail if the request to Nintendo's servers fails, or if
+/// Nintendo Accounts rejects the `access_token`.
 pub async fn get_user_info(
-    access_token: &str,
-    client: &Client,
-) -> Result<UserInfo, reqwest::Error> {
-    let mut rv: UserInfo = client
-        .get("https://api.accounts.nintendo.com/2.0.0/users/me")
+    access_token: &AccessToken,
+    client: &NsoClient,
+) -> Result<UserInfo, NsoError> {
+    let request = client
+        .get(format!(
+            "{}/2.0.0/users/me",
+            client.endpoints().accounts_api
+        ))
         .header(USER_AGENT, ONLINE_LOUNGE_USER_AGENT)
         .header(ACCEPT_LANGUAGE, "en-US")
         .header(ACCEPT, "application/json")
-        .bearer_auth(access_token)
-        .header(HOST, "api.accounts.nintendo.com")
+        .bearer_auth(access_token.expose())
         .header(CONNECTION, "Keep-Alive")
-        .header(ACCEPT_ENCODING, "gzip")
-        .send()
-        .await?
-        .json()
-        .await?;
-    rv.rest.clear();
-    rv.rest.shrink_to_fit();
-    Ok(rv)
+        .header(ACCEPT_ENCODING, "gzip");
+    let response = send("accounts.user_info", request, client).await?;
+    accounts_json(response).await
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct NsoFToken {
     pub f: String,
     pub timestamp: i64,
     pub request_id: String,
 }
 
-#[derive(Serialize)]
-struct FTokenApiBody<'a> {
-    hash_method: &'static str,
-    token: &'a str,
-}
-impl<'a> FTokenApiBody<'a> {
-    fn step_1(id_token: &'a str) -> Self {
+impl From<FToken> for NsoFToken {
+    fn from(f: FToken) -> Self {
         Self {
-            hash_method: "1",
-            token: id_token,
-        }
-    }
-
-    fn step_2(login_token: &'a str) -> Self {
-        Self {
-            hash_method: "2",
-            token: login_token,
+            f: f.f,
+            timestamp: f.timestamp,
+            request_id: f.request_id,
         }
     }
 }
@@ -310,37 +512,59 @@ impl<'a> FTokenApiBody<'a> {
 ///
 /// # Errors
 ///
-/// If the request to imink fails.
-pub async fn get_f1(
-    id_token: &str,
-    client: &Client,
-) -> Result<NsoFToken, reqwest::Error> {
-    // TODO: Reverse-engineer libvoip so we don't depend on imink
-    client
-        .post("https://api.imink.app/f")
-        .header(USER_AGENT, RUST_NSO_USER_AGENT)
-        .json(&FTokenApiBody::step_1(id_token))
-        .send()
+/// If the request to the provider fails, or if it cannot generate an f-token.
+pub async fn get_f1<P: FTokenProvider + ?Sized>(
+    provider: &P,
+    id_token: &IdToken,
+    client: &NsoClient,
+) -> Result<NsoFToken, NsoError> {
+    Ok(get_f(provider, HashMethod::Nso, id_token.expose(), client)
         .await?
-        .json()
-        .await
+        .into())
+}
+
+/// Request an f-token from `provider`, recording which provider was used if
+/// the `tracing` feature is enabled
+async fn get_f<P: FTokenProvider + ?Sized>(
+    provider: &P,
+    hash_method: HashMethod,
+    token: &str,
+    client: &NsoClient,
+) -> Result<FToken, NsoError> {
+    let f = provider.get_f(hash_method, token, client);
+    #[cfg(feature = "tracing")]
+    let f = tracing::Instrument::instrument(
+        f,
+        tracing::info_span!(
+            "f_token",
+            provider = provider.name(),
+            hash_method = ?hash_method,
+        ),
+    );
+    f.await
+}
+
+/// The credential Coral issues on login, used to authenticate every other
+/// Coral request
+#[derive(Clone, Debug)]
+pub struct LoginToken {
+    pub access_token: CoralToken,
+    /// The number of seconds the token is valid for
+    pub expires_in: u64,
 }
 
 /// Get a login token based on the first f-token
 ///
 /// # Errors
 ///
-/// This function will fail if the request to Nintendo's servers fails.
+/// This function will fail if the request to Nintendo's servers fails, or if
+/// Coral rejects the login (for example, if the f-token is invalid).
 pub async fn get_login_token(
     f1: &NsoFToken,
-    id_token: &str,
+    id_token: &IdToken,
     user_info: &UserInfo,
-    client: &Client,
-) -> Result<String, reqwest::Error> {
-    #[derive(Serialize)]
-    struct Body<'a> {
-        parameter: Parameter<'a>,
-    }
+    client: &NsoClient,
+) -> Result<LoginToken, NsoError> {
     #[derive(Serialize)]
     #[allow(non_snake_case)]
     struct Parameter<'a> {
@@ -353,12 +577,6 @@ pub async fn get_login_token(
         language: &'a str,
     }
 
-    #[derive(Deserialize)]
-    struct Resp {
-        result: Result,
-        #[serde(flatten)]
-        _rest: HashMap<String, Value>,
-    }
     #[derive(Deserialize)]
     #[allow(non_snake_case)]
     struct Result {
@@ -369,65 +587,85 @@ pub async fn get_login_token(
     #[derive(Deserialize)]
     #[allow(non_snake_case)]
     struct Credential {
-        accessToken: String,
+        accessToken: CoralToken,
+        expiresIn: u64,
         #[serde(flatten)]
         _rest: HashMap<String, Value>,
     }
 
-    Ok(client
-        .post("https://api-lp1.znc.srv.nintendo.net/v3/Account/Login")
-        .header(HOST, "api-lp1.znc.srv.nintendo.net")
-        .header(ACCEPT_LANGUAGE, "en-US")
-        .header(USER_AGENT, NSO_USER_AGENT)
-        .header(ACCEPT, "application/json")
-        .header("X-ProductVersion", NSO_VERSION)
-        .header(CONNECTION, "Keep-Alive")
-        .header(AUTHORIZATION, "Bearer")
-        .header("X-Platform", "Android")
-        .header(ACCEPT_ENCODING, "gzip")
-        .json(&Body {
-            parameter: Parameter {
-                f: &f1.f,
-                timestamp: f1.timestamp,
-                requestId: &f1.request_id,
-                naIdToken: id_token,
-                naCountry: &user_info.country,
-                naBirthday: &user_info.birthday,
-                language: &user_info.language,
-            },
-        })
-        .send()
-        .await?
-        .json::<Resp>()
-        .await?
-        .result
-        .webApiServerCredential
-        .accessToken)
+    let credential = coral::call::<_, Result>(
+        "/v3/Account/Login",
+        None,
+        Parameter {
+            f: &f1.f,
+            timestamp: f1.timestamp,
+            requestId: &f1.request_id,
+            naIdToken: id_token.expose(),
+            naCountry: &user_info.country,
+            naBirthday: &user_info.birthday,
+            language: &user_info.language,
+        },
+        client,
+    )
+    .await?
+    .webApiServerCredential;
+    Ok(LoginToken {
+        access_token: credential.accessToken,
+        expires_in: credential.expiresIn,
+    })
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct AppFToken {
     pub f: String,
     pub timestamp: i64,
     pub request_id: String,
 }
 
+impl From<FToken> for AppFToken {
+    fn from(f: FToken) -> Self {
+        Self {
+            f: f.f,
+            timestamp: f.timestamp,
+            request_id: f.request_id,
+        }
+    }
+}
+
 /// Get an application f-token, from the provided `login_token`
 ///
 /// # Errors
 ///
-/// If the request to imink fails.
-pub async fn get_f2(
-    login_token: &str,
-    client: &Client,
-) -> Result<AppFToken, reqwest::Error> {
-    // TODO: Reverse-engineer libvoip so we don't depend on imink
-    client
-        .post("https://api.imink.app/f")
-        .header(USER_AGENT, RUST_NSO_USER_AGENT)
-        .json(&FTokenApiBody::step_2(login_token))
-        .send()
-        .await?
-        .json()
-        .await
+/// If the request to the provider fails, or if it cannot generate an f-token.
+pub async fn get_f2<P: FTokenProvider + ?Sized>(
+    provider: &P,
+    login_token: &CoralToken,
+    client: &NsoClient,
+) -> Result<AppFToken, NsoError> {
+    Ok(
+        get_f(provider, HashMethod::App, login_token.expose(), client)
+            .await?
+            .into(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn user_info_without_optional_fields_is_parsed() {
+        let user_info: UserInfo = serde_json::from_str(include_str!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/accounts/user_info_minimal.json",
+        )))
+        .expect("Failed to parse user info");
+        assert_eq!(user_info.id, "0123456789abcdef");
+        assert_eq!(user_info.language, "en-GB");
+        assert!(user_info.gender.is_none());
+        assert!(user_info.timezone.is_none());
+        assert!(user_info.is_child.is_none());
+        assert!(user_info.created_at.is_none());
+        assert!(user_info.candidate_miis.is_empty());
+    }
 }
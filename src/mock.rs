@@ -0,0 +1,852 @@
+//! A local server emulating every endpoint used by the crate, for testing
+//! without a Nintendo Account
+//!
+//! The server answers with the canned values in [`fixtures`], checking that
+//! each request carries the token issued by the previous step of the login
+//! chain. Failures can be injected per [`MockRoute`] to exercise error paths.
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use std::{fmt, io};
+
+use hyper::header::{AUTHORIZATION, CONTENT_TYPE, COOKIE, SET_COOKIE};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use serde_json::{json, Value};
+use tokio::sync::oneshot;
+
+use crate::splatoon3::keys;
+use crate::splatoon3::queries::QueryRegistry;
+use crate::{splatoon2, splatoon3, Endpoints, NsoClient};
+
+/// The canned values served by [`MockServer`]
+pub mod fixtures {
+    /// An unsigned `session_token` for [`NA_ID`], expiring in 2100
+    pub const SESSION_TOKEN: &str = concat!(
+        "eyJ0eXAiOiJKV1QiLCJhbGciOiJSUzI1NiIsImtpZCI6Im1vY2sifQ.",
+        "eyJqdGkiOiJtb2NrLXNlc3Npb24tdG9rZW4tanRpIiwidHlwIjoic2Vzc2lvbl90b2tl",
+        "biIsImlzcyI6Imh0dHBzOi8vYWNjb3VudHMubmludGVuZG8uY29tIiwic3Q6c2NwIjpb",
+        "MCw4LDksMTcsMjNdLCJzdWIiOiIwMTIzNDU2Nzg5YWJjZGVmIiwiZXhwIjo0MTAyNDQ0",
+        "ODAwLCJhdWQiOiI3MWI5NjNjMWI3YjZkMTE5IiwiaWF0IjoxNzAwMDAwMDAwfQ.",
+        "bW9jaw",
+    );
+    pub const ACCESS_TOKEN: &str = "mock-access-token";
+    /// An unsigned `id_token` for [`NA_ID`], expiring in 2100
+    pub const ID_TOKEN: &str = concat!(
+        "eyJ0eXAiOiJKV1QiLCJhbGciOiJSUzI1NiIsImtpZCI6Im1vY2sifQ.",
+        "eyJhdWQiOiI3MWI5NjNjMWI3YjZkMTE5IiwiaWF0IjoxNzAwMDAwMDAwLCJ0eXAiOiJp",
+        "ZF90b2tlbiIsInN1YiI6IjAxMjM0NTY3ODlhYmNkZWYiLCJpc3MiOiJodHRwczovL2Fj",
+        "Y291bnRzLm5pbnRlbmRvLmNvbSIsImp0aSI6Im1vY2staWQtdG9rZW4tanRpIiwiZXhw",
+        "Ijo0MTAyNDQ0ODAwfQ.",
+        "bW9jaw",
+    );
+    pub const LOGIN_TOKEN: &str = "mock-login-token";
+    pub const WEB_TOKEN: &str = "mock-web-token";
+    pub const BULLET_TOKEN: &str = "mock-bullet-token";
+    pub const IKSM_SESSION: &str = "mock-iksm-session";
+    pub const F: &str = "mock-f";
+    pub const NA_ID: &str = "0123456789abcdef";
+    pub const NICKNAME: &str = "Mock User";
+    pub const COUNTRY: &str = "GB";
+    pub const BIRTHDAY: &str = "1990-01-01";
+    pub const LANGUAGE: &str = "en-GB";
+    /// The Nintendo Switch account ID of the user's only friend
+    pub const FRIEND_NSA_ID: &str = "0011223344556677";
+    /// The name of the user's only friend
+    pub const FRIEND_NAME: &str = "Mock Friend";
+    /// The user's friend code
+    pub const FRIEND_CODE: &str = "SW-1234-5678-9012";
+    /// The friend code of a user who isn't yet a friend
+    pub const STRANGER_FRIEND_CODE: &str = "SW-2345-6789-0123";
+    /// The Nintendo Switch account ID of the user with
+    /// [`STRANGER_FRIEND_CODE`]
+    pub const STRANGER_NSA_ID: &str = "8899aabbccddeeff";
+    /// The SplatNet 3 web view version found in the mock `main.*.js`
+    pub const WEB_VIEW_VERSION: &str = "9.9.9-01234567";
+    /// A query which is only listed in the mock `main.*.js`, not in
+    /// [`keys`](crate::splatoon3::keys)
+    pub const HOME_QUERY: &str = "HomeQuery";
+    /// The hash of [`HOME_QUERY`]
+    pub const HOME_QUERY_HASH: &str = concat!(
+        "0123456789abcdef0123456789abcdef",
+        "0123456789abcdef0123456789abcdef",
+    );
+}
+
+/// An endpoint served by [`MockServer`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MockRoute {
+    /// `POST /connect/1.0.0/api/session_token`
+    SessionToken,
+    /// `POST /connect/1.0.0/api/token`
+    Token,
+    /// `GET /2.0.0/users/me`
+    UserInfo,
+    /// `POST /v3/Account/Login`
+    AccountLogin,
+    /// `POST /v2/Game/GetWebServiceToken`
+    WebServiceToken,
+    /// `POST /v3/Friend/List`
+    FriendList,
+    /// `POST /v1/Game/ListWebServices`
+    WebServices,
+    /// `POST /v3/User/ShowSelf`
+    ShowSelf,
+    /// `POST /v3/Friend/CreateFriendCodeUrl`
+    FriendCodeUrl,
+    /// `POST /v3/Friend/GetUserByFriendCode`
+    UserByFriendCode,
+    /// `POST /v3/FriendRequest/Create`
+    FriendRequest,
+    /// `POST /v3/User/Permissions/ShowSelf`
+    Permissions,
+    /// `POST /v3/User/Permissions/UpdateSelf`
+    UpdatePermissions,
+    /// `POST /v3/Friend/Favorite/Create`
+    FavouriteFriend,
+    /// `POST /v3/Friend/Favorite/Delete`
+    UnfavouriteFriend,
+    /// `POST /v3/Friend/Delete`
+    DeleteFriend,
+    /// `POST /f` (imink) and `POST /api/znca/f` (nxapi-znca-api)
+    FToken,
+    /// `GET /` (the SplatNet 2 page which sets `iksm_session`, and the
+    /// SplatNet 3 web view page)
+    Splatoon2,
+    /// `GET /static/js/main.*.js` (the SplatNet 3 web view script)
+    MainScript,
+    /// `POST /api/bullet_tokens`
+    BulletToken,
+    /// `POST /api/graphql`
+    GraphQl,
+}
+
+impl MockRoute {
+    fn from_request(method: &Method, path: &str) -> Option<Self> {
+        Some(match (method, path) {
+            (&Method::POST, "/connect/1.0.0/api/session_token") => Self::SessionToken,
+            (&Method::POST, "/connect/1.0.0/api/token") => Self::Token,
+            (&Method::GET, "/2.0.0/users/me") => Self::UserInfo,
+            (&Method::POST, "/v3/Account/Login") => Self::AccountLogin,
+            (&Method::POST, "/v2/Game/GetWebServiceToken") => Self::WebServiceToken,
+            (&Method::POST, "/v3/Friend/List") => Self::FriendList,
+            (&Method::POST, "/v1/Game/ListWebServices") => Self::WebServices,
+            (&Method::POST, "/v3/User/ShowSelf") => Self::ShowSelf,
+            (&Method::POST, "/v3/Friend/CreateFriendCodeUrl") => Self::FriendCodeUrl,
+            (&Method::POST, "/v3/Friend/GetUserByFriendCode") => Self::UserByFriendCode,
+            (&Method::POST, "/v3/FriendRequest/Create") => Self::FriendRequest,
+            (&Method::POST, "/v3/User/Permissions/ShowSelf") => Self::Permissions,
+            (&Method::POST, "/v3/User/Permissions/UpdateSelf") => {
+                Self::UpdatePermissions
+            },
+            (&Method::POST, "/v3/Friend/Favorite/Create") => Self::FavouriteFriend,
+            (&Method::POST, "/v3/Friend/Favorite/Delete") => Self::UnfavouriteFriend,
+            (&Method::POST, "/v3/Friend/Delete") => Self::DeleteFriend,
+            (&Method::POST, "/f" | "/api/znca/f") => Self::FToken,
+            (&Method::GET, "/") => Self::Splatoon2,
+            (&Method::GET, path) if path.starts_with("/static/js/main.") => {
+                Self::MainScript
+            },
+            (&Method::POST, "/api/bullet_tokens") => Self::BulletToken,
+            (&Method::POST, "/api/graphql") => Self::GraphQl,
+            _ => return None,
+        })
+    }
+}
+
+/// A failure to inject into a [`MockRoute`]
+#[derive(Debug, Clone)]
+pub enum MockFailure {
+    /// Respond with this HTTP status
+    Status(u16),
+    /// Respond with HTTP 200 and this body
+    Body(String),
+    /// Respond with a Coral error envelope with this `status`
+    Coral(u32),
+    /// Wait this long, then respond normally
+    Delay(Duration),
+}
+
+#[derive(Default)]
+struct State {
+    /// Each route's failure, and how many more requests it applies to
+    /// (`None` meaning forever)
+    failures: HashMap<MockRoute, (MockFailure, Option<usize>)>,
+    hits: HashMap<MockRoute, usize>,
+    graphql: HashMap<String, Value>,
+    /// The persisted queries listed in `main.*.js`
+    queries: QueryRegistry,
+    friends: Vec<Value>,
+    /// Who can see the user's presence, and the etag of that setting
+    presence_permission: String,
+    permissions_etag: u32,
+}
+
+impl State {
+    fn take_failure(&mut self, route: MockRoute) -> Option<MockFailure> {
+        let (failure, remaining) = self.failures.get_mut(&route)?;
+        let failure = failure.clone();
+        match remaining {
+            Some(1) => {
+                self.failures.remove(&route);
+            },
+            Some(remaining) => *remaining -= 1,
+            None => {},
+        }
+        Some(failure)
+    }
+}
+
+/// A running mock server, shut down when dropped
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl fmt::Debug for MockServer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MockServer")
+            .field("addr", &self.addr)
+            .finish()
+    }
+}
+
+impl MockServer {
+    /// Start a server on a free local port
+    ///
+    /// Every query in [`keys`] (and [`fixtures::HOME_QUERY`]) answers with
+    /// `{"data": {}}` until given a
+    /// response with [`set_graphql_response`](Self::set_graphql_response);
+    /// any other query hash answers with `PersistedQueryNotFound`.
+    ///
+    /// # Errors
+    ///
+    /// If the port cannot be bound.
+    pub async fn start() -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+        let mut queries = QueryRegistry::bundled();
+        queries.insert(fixtures::HOME_QUERY, fixtures::HOME_QUERY_HASH);
+        let state = Arc::new(Mutex::new(State {
+            graphql: [
+                keys::SCHEDULES,
+                keys::SPLATNET,
+                keys::SALMON,
+                keys::ORDER,
+                keys::SPLATFEST_OVERVIEW,
+                keys::SPLATFEST,
+                keys::LATEST_BATTLES,
+                keys::GEAR,
+                fixtures::HOME_QUERY_HASH,
+            ]
+            .into_iter()
+            .map(|hash| (hash.to_string(), json!({"data": {}})))
+            .collect(),
+            queries,
+            friends: vec![friend(json!({
+                "state": "PLAYING",
+                "updatedAt": 1_700_000_000,
+                "logoutAt": 1_699_990_000,
+                "game": {
+                    "name": "Splatoon 3",
+                    "imageUri": "https://example.com/splatoon3.jpg",
+                    "shopUri": "https://example.com/splatoon3",
+                    "totalPlayTime": 6000,
+                    "firstPlayedAt": 1_662_000_000,
+                    "sysDescription": "",
+                },
+            }))],
+            presence_permission: "FRIENDS".to_string(),
+            ..State::default()
+        }));
+        let service_state = state.clone();
+        let make_service = make_service_fn(move |_| {
+            let state = service_state.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    handle(state.clone(), request)
+                }))
+            }
+        });
+        let (shutdown, shutdown_rx) = oneshot::channel();
+        let server = Server::from_tcp(listener)
+            .map_err(io::Error::other)?
+            .serve(make_service)
+            .with_graceful_shutdown(async {
+                shutdown_rx.await.ok();
+            });
+        tokio::spawn(server);
+        Ok(Self {
+            addr,
+            state,
+            shutdown: Some(shutdown),
+        })
+    }
+
+    /// The base URL of the server
+    #[must_use]
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// [`Endpoints`] pointing every host at the server
+    #[must_use]
+    pub fn endpoints(&self) -> Endpoints {
+        Endpoints::all_at(&self.url())
+    }
+
+    /// A client which sends every request to the server
+    #[must_use]
+    pub fn client(&self) -> NsoClient {
+        NsoClient::default().with_endpoints(self.endpoints())
+    }
+
+    /// Make every request to `route` fail with `failure`
+    pub fn fail(&self, route: MockRoute, failure: MockFailure) {
+        self.lock().failures.insert(route, (failure, None));
+    }
+
+    /// Make the next `times` requests to `route` fail with `failure`
+    pub fn fail_times(&self, route: MockRoute, failure: MockFailure, times: usize) {
+        if times > 0 {
+            self.lock().failures.insert(route, (failure, Some(times)));
+        }
+    }
+
+    /// Stop injecting failures
+    pub fn clear_failures(&self) {
+        self.lock().failures.clear();
+    }
+
+    /// How many requests `route` has received
+    #[must_use]
+    pub fn hits(&self, route: MockRoute) -> usize {
+        self.lock().hits.get(&route).copied().unwrap_or_default()
+    }
+
+    /// Answer the persisted query `hash` with `response`
+    pub fn set_graphql_response(&self, hash: impl Into<String>, response: Value) {
+        self.lock().graphql.insert(hash.into(), response);
+    }
+
+    /// Set the presence of the user's only friend, as sent by Coral
+    pub fn set_friend_presence(&self, presence: Value) {
+        self.lock().friends = vec![friend(presence)];
+    }
+
+    /// Simulate a web view update changing the hash of the persisted query
+    /// `name` to `hash`
+    ///
+    /// The old hash answers with `PersistedQueryNotFound` from now on, and
+    /// `main.*.js` lists the new one.
+    pub fn rotate_query(&self, name: &str, hash: impl Into<String>) {
+        let hash = hash.into();
+        let mut state = self.lock();
+        let response = state
+            .queries
+            .get(name)
+            .map(str::to_string)
+            .and_then(|old| state.graphql.remove(&old))
+            .unwrap_or_else(|| json!({"data": {}}));
+        state.graphql.insert(hash.clone(), response);
+        state.queries.insert(name, hash);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().expect("mock server state poisoned")
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            shutdown.send(()).ok();
+        }
+    }
+}
+
+fn respond(status: StatusCode, body: &Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .expect("mock response is valid")
+}
+
+fn ok(body: &Value) -> Response<Body> {
+    respond(StatusCode::OK, body)
+}
+
+fn coral_ok(result: &Value) -> Response<Body> {
+    ok(&json!({
+        "status": 0,
+        "result": result,
+        "correlationId": "mock-correlation-id",
+    }))
+}
+
+fn coral_error(status: u32, message: &str) -> Response<Body> {
+    ok(&json!({
+        "status": status,
+        "errorMessage": message,
+        "correlationId": "mock-correlation-id",
+    }))
+}
+
+fn accounts_error(error: &str, description: &str) -> Response<Body> {
+    respond(
+        StatusCode::BAD_REQUEST,
+        &json!({"error": error, "error_description": description}),
+    )
+}
+
+fn unauthorised() -> Response<Body> {
+    respond(
+        StatusCode::UNAUTHORIZED,
+        &json!({"error": "invalid_token", "error_description": "bad token"}),
+    )
+}
+
+fn has_bearer(request: &Request<Body>, token: &str) -> bool {
+    request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        == Some(token)
+}
+
+fn has_gtoken(request: &Request<Body>) -> bool {
+    request
+        .headers()
+        .get(COOKIE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|cookies| {
+            cookies.split(';').any(|cookie| {
+                cookie.trim().strip_prefix("_gtoken=") == Some(fixtures::WEB_TOKEN)
+            })
+        })
+}
+
+async fn handle(
+    state: Arc<Mutex<State>>,
+    request: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let Some(route) = MockRoute::from_request(request.method(), request.uri().path())
+    else {
+        return Ok(respond(
+            StatusCode::NOT_FOUND,
+            &json!({"error": "not_found"}),
+        ));
+    };
+    let failure = {
+        let mut state = state.lock().expect("mock server state poisoned");
+        *state.hits.entry(route).or_default() += 1;
+        state.take_failure(route)
+    };
+    match failure {
+        Some(MockFailure::Status(status)) => {
+            let status = StatusCode::from_u16(status)
+                .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+            return Ok(respond(
+                status,
+                &json!({"error": true, "reason": "injected failure"}),
+            ));
+        },
+        Some(MockFailure::Body(body)) => {
+            return Ok(Response::new(Body::from(body)));
+        },
+        Some(MockFailure::Coral(status)) => {
+            return Ok(coral_error(status, "injected failure"));
+        },
+        Some(MockFailure::Delay(delay)) => tokio::time::sleep(delay).await,
+        None => {},
+    }
+    let authorised = match route {
+        MockRoute::UserInfo => has_bearer(&request, fixtures::ACCESS_TOKEN),
+        MockRoute::WebServiceToken
+        | MockRoute::FriendList
+        | MockRoute::WebServices
+        | MockRoute::ShowSelf
+        | MockRoute::FriendCodeUrl
+        | MockRoute::UserByFriendCode
+        | MockRoute::FriendRequest
+        | MockRoute::Permissions
+        | MockRoute::UpdatePermissions
+        | MockRoute::FavouriteFriend
+        | MockRoute::UnfavouriteFriend
+        | MockRoute::DeleteFriend => has_bearer(&request, fixtures::LOGIN_TOKEN),
+        MockRoute::BulletToken => has_gtoken(&request),
+        MockRoute::GraphQl => {
+            has_bearer(&request, fixtures::BULLET_TOKEN) && has_gtoken(&request)
+        },
+        _ => true,
+    };
+    let body = hyper::body::to_bytes(request.into_body())
+        .await
+        .unwrap_or_default();
+    let json_body: Value = serde_json::from_slice(&body).unwrap_or_default();
+    Ok(match route {
+        MockRoute::SessionToken => ok(&json!({
+            "session_token": fixtures::SESSION_TOKEN,
+            "code": "mock-session-token-code",
+        })),
+        MockRoute::Token => {
+            if json_body["session_token"] == fixtures::SESSION_TOKEN {
+                ok(&json!({
+                    "access_token": fixtures::ACCESS_TOKEN,
+                    "id_token": fixtures::ID_TOKEN,
+                    "expires_in": 900,
+                    "token_type": "Bearer",
+                    "scope": [
+                        "openid",
+                        "user",
+                        "user.birthday",
+                        "user.mii",
+                        "user.screenName",
+                    ],
+                }))
+            } else {
+                accounts_error("invalid_grant", "The provided grant is invalid")
+            }
+        },
+        MockRoute::UserInfo if !authorised => unauthorised(),
+        MockRoute::UserInfo => ok(&user_info()),
+        MockRoute::AccountLogin => {
+            let parameter = &json_body["parameter"];
+            if parameter["naIdToken"] != fixtures::ID_TOKEN
+                || parameter["f"] != fixtures::F
+            {
+                coral_error(9403, "Invalid token.")
+            } else {
+                coral_ok(&json!({
+                    "user": {
+                        "id": 1_234_567_890_u64,
+                        "nsaId": "fedcba9876543210",
+                        "name": fixtures::NICKNAME,
+                        "imageUri": "https://example.com/mock-user.png",
+                    },
+                    "webApiServerCredential": {
+                        "accessToken": fixtures::LOGIN_TOKEN,
+                        "expiresIn": 7200,
+                    },
+                    "firebaseCredential": {
+                        "accessToken": "",
+                        "expiresIn": 3600,
+                    },
+                }))
+            }
+        },
+        MockRoute::WebServiceToken if !authorised => {
+            coral_error(9404, "Token expired.")
+        },
+        MockRoute::WebServiceToken => coral_ok(&json!({
+            "accessToken": fixtures::WEB_TOKEN,
+            "expiresIn": 7200,
+        })),
+        MockRoute::WebServices
+        | MockRoute::FriendList
+        | MockRoute::ShowSelf
+        | MockRoute::FriendCodeUrl
+        | MockRoute::UserByFriendCode
+        | MockRoute::FriendRequest
+        | MockRoute::Permissions
+        | MockRoute::UpdatePermissions
+        | MockRoute::FavouriteFriend
+        | MockRoute::UnfavouriteFriend
+        | MockRoute::DeleteFriend
+            if !authorised =>
+        {
+            coral_error(9404, "Token expired.")
+        },
+        MockRoute::WebServices => coral_ok(&web_services()),
+        MockRoute::ShowSelf => coral_ok(&json!({
+            "id": 1_234_567_890_u64,
+            "nsaId": "fedcba9876543210",
+            "imageUri": "https://example.com/mock-user.png",
+            "image2Uri": "https://example.com/mock-user-2.png",
+            "name": fixtures::NICKNAME,
+            "supportId": "0000-0000-0000-0000-0000-0",
+            "isChildRestricted": false,
+            "etag": "mock-etag",
+            "links": {
+                "nintendoAccount": {"membership": {"active": true}},
+                "friendCode": {
+                    "regenerable": false,
+                    "regenerableAt": 1_700_000_000,
+                    "id": fixtures::FRIEND_CODE.trim_start_matches("SW-"),
+                },
+            },
+            "permissions": {"presence": "FRIENDS"},
+            "presence": {
+                "state": "OFFLINE",
+                "updatedAt": 0,
+                "logoutAt": 0,
+                "game": {},
+            },
+        })),
+        MockRoute::FriendCodeUrl => coral_ok(&json!({
+            "url": "https://lounge.nintendo.com/friendcode/1234-5678-9012/mock",
+            "friendCode": fixtures::FRIEND_CODE.trim_start_matches("SW-"),
+        })),
+        MockRoute::UserByFriendCode => {
+            let stranger = fixtures::STRANGER_FRIEND_CODE.trim_start_matches("SW-");
+            if json_body["parameter"]["friendCode"] == stranger {
+                coral_ok(&json!({
+                    "id": 1_122_334_455_u64,
+                    "nsaId": fixtures::STRANGER_NSA_ID,
+                    "imageUri": "https://example.com/mock-stranger.png",
+                    "image2Uri": "https://example.com/mock-stranger-2.png",
+                    "name": "Mock Stranger",
+                    "extras": {},
+                }))
+            } else {
+                coral_error(9402, "Resource not found.")
+            }
+        },
+        MockRoute::FriendRequest => {
+            if json_body["parameter"]["nsaId"] == fixtures::STRANGER_NSA_ID {
+                coral_ok(&json!({}))
+            } else {
+                coral_error(9400, "Bad request.")
+            }
+        },
+        MockRoute::Permissions => {
+            let state = state.lock().expect("mock server state poisoned");
+            coral_ok(&json!({
+                "etag": format!("mock-etag-{}", state.permissions_etag),
+                "permissions": {"presence": state.presence_permission},
+            }))
+        },
+        MockRoute::UpdatePermissions => {
+            let mut state = state.lock().expect("mock server state poisoned");
+            let parameter = &json_body["parameter"];
+            let etag = format!("mock-etag-{}", state.permissions_etag);
+            match parameter["permissions"]["presence"].as_str() {
+                Some(presence) if parameter["etag"] == etag => {
+                    state.presence_permission = presence.to_string();
+                    state.permissions_etag += 1;
+                    coral_ok(&Value::Null)
+                },
+                _ => coral_error(9400, "Bad request."),
+            }
+        },
+        MockRoute::FavouriteFriend
+        | MockRoute::UnfavouriteFriend
+        | MockRoute::DeleteFriend => {
+            let mut state = state.lock().expect("mock server state poisoned");
+            let nsa_id = &json_body["parameter"]["nsaId"];
+            match state
+                .friends
+                .iter()
+                .position(|friend| friend["nsaId"] == *nsa_id)
+            {
+                Some(index) if route == MockRoute::DeleteFriend => {
+                    state.friends.remove(index);
+                    coral_ok(&Value::Null)
+                },
+                Some(index) => {
+                    state.friends[index]["isFavoriteFriend"] =
+                        Value::Bool(route == MockRoute::FavouriteFriend);
+                    coral_ok(&Value::Null)
+                },
+                None => coral_error(9402, "Resource not found."),
+            }
+        },
+        MockRoute::FriendList => coral_ok(&json!({
+            "friends": state.lock().expect("mock server state poisoned").friends,
+        })),
+        MockRoute::FToken => {
+            if json_body["token"].is_string() {
+                ok(&json!({
+                    "f": fixtures::F,
+                    "timestamp": 1_700_000_000_000_i64,
+                    "request_id": "mock-request-id",
+                }))
+            } else {
+                respond(
+                    StatusCode::BAD_REQUEST,
+                    &json!({"error": true, "reason": "token is required"}),
+                )
+            }
+        },
+        MockRoute::Splatoon2 => Response::builder()
+            .header(CONTENT_TYPE, "text/html")
+            .header(
+                SET_COOKIE,
+                format!("iksm_session={}; Path=/; HttpOnly", fixtures::IKSM_SESSION,),
+            )
+            .body(Body::from(concat!(
+                "<!DOCTYPE html><html><head>",
+                r#"<script defer="defer" src="/static/js/main.0123abcd.js">"#,
+                "</script></head></html>",
+            )))
+            .expect("mock response is valid"),
+        MockRoute::MainScript => Response::builder()
+            .header(CONTENT_TYPE, "application/javascript")
+            .body(Body::from(main_script(
+                &state.lock().expect("mock server state poisoned").queries,
+            )))
+            .expect("mock response is valid"),
+        MockRoute::BulletToken if !authorised => unauthorised(),
+        MockRoute::BulletToken => respond(
+            StatusCode::CREATED,
+            &json!({
+                "bulletToken": fixtures::BULLET_TOKEN,
+                "lang": fixtures::LANGUAGE,
+                "is_noe_country": "true",
+            }),
+        ),
+        MockRoute::GraphQl if !authorised => unauthorised(),
+        MockRoute::GraphQl => {
+            let hash = json_body["extensions"]["persistedQuery"]["sha256Hash"]
+                .as_str()
+                .unwrap_or_default();
+            let state = state.lock().expect("mock server state poisoned");
+            ok(state.graphql.get(hash).unwrap_or(&json!({
+                "errors": [{
+                    "message": "PersistedQueryNotFound",
+                    "extensions": {"code": "PERSISTED_QUERY_NOT_FOUND"},
+                }],
+            })))
+        },
+    })
+}
+
+/// A fragment of the web view's `main.*.js`, containing the revision info and
+/// every persisted query
+fn main_script(queries: &QueryRegistry) -> String {
+    let (version, revision) = fixtures::WEB_VIEW_VERSION
+        .split_once('-')
+        .expect("fixture version has a revision");
+    let mut script = format!(
+        concat!(
+            r#"const r="{revision}00000000000000000000000000000000","#,
+            r#"o=void 0!==r?r:"revision_info_not_set"}}`,"#,
+            "a=`{version}-${{o.substring(0,8)}}`;",
+        ),
+        revision = revision,
+        version = version,
+    );
+    for (name, hash) in queries.iter() {
+        script.push_str(&format!(
+            concat!(
+                r#"const {name}={{params:{{id:"{hash}",metadata:{{}},"#,
+                r#"name:"{name}",operationKind:"query",text:""}}}};"#,
+            ),
+            name = name,
+            hash = hash,
+        ));
+    }
+    script
+}
+
+fn web_services() -> Value {
+    json!([
+        {
+            "id": splatoon3::GAME_ID,
+            "name": "Splatoon 3",
+            "uri": "https://api.lp1.av5ja.srv.nintendo.net",
+            "imageUri": "https://example.com/splatoon3-service.jpg",
+            "whiteList": ["api.lp1.av5ja.srv.nintendo.net"],
+            "customAttributes": [
+                {"attrKey": "verifyMembership", "attrValue": "true"},
+                {"attrKey": "deepLinkingEnabled", "attrValue": "true"},
+            ],
+        },
+        {
+            "id": splatoon2::GAME_ID,
+            "name": "Splatoon 2",
+            "uri": "https://app.splatoon2.nintendo.net",
+            "imageUri": "https://example.com/splatoon2-service.jpg",
+            "whiteList": ["app.splatoon2.nintendo.net"],
+            "customAttributes": [
+                {"attrKey": "verifyMembership", "attrValue": "true"},
+            ],
+        },
+    ])
+}
+
+/// The user's only friend, with `presence`
+fn friend(presence: Value) -> Value {
+    json!({
+        "id": 9_876_543_210_u64,
+        "nsaId": fixtures::FRIEND_NSA_ID,
+        "imageUri": "https://example.com/mock-friend.png",
+        "image2Uri": "https://example.com/mock-friend-2.png",
+        "name": fixtures::FRIEND_NAME,
+        "isFriend": true,
+        "isFavoriteFriend": false,
+        "isServiceUser": true,
+        "isNew": false,
+        "friendCreatedAt": 1_650_000_000,
+        "route": {
+            "appName": "",
+            "userName": "",
+            "shopUri": "",
+            "imageUri": "",
+            "channel": "friendCode",
+        },
+        "presence": presence,
+    })
+}
+
+fn user_info() -> Value {
+    json!({
+        "id": fixtures::NA_ID,
+        "nickname": fixtures::NICKNAME,
+        "screenName": "mo••••@example.com",
+        "country": fixtures::COUNTRY,
+        "birthday": fixtures::BIRTHDAY,
+        "language": fixtures::LANGUAGE,
+        "gender": "unknown",
+        "region": null,
+        "isChild": false,
+        "timezone": {
+            "id": "Europe/London",
+            "name": "Europe/London",
+            "utcOffset": "+00:00",
+            "utcOffsetSeconds": 0,
+        },
+        "mii": {
+            "id": "0123456789abcdef0123456789abcdef",
+            "clientId": "1cfe3a55ed8924d9",
+            "etag": "mock-etag",
+            "favoriteColor": "red",
+            "imageOrigin": "https://example.com/mii.png",
+            "imageUriTemplate": "https://example.com/mii/{}.png",
+            "type": "profile",
+            "coreData": {"4": "mock-core-data"},
+            "storeData": {"3": "mock-store-data"},
+            "updatedAt": 1_600_000_000,
+        },
+        "candidateMiis": [],
+        "analyticsOptedIn": false,
+        "analyticsOptedInUpdatedAt": 1_600_000_000,
+        "analyticsPermissions": {
+            "internalAnalysis": {
+                "permitted": false,
+                "updatedAt": 1_600_000_000,
+            },
+            "targetMarketing": {
+                "permitted": false,
+                "updatedAt": 1_600_000_000,
+            },
+        },
+        "clientFriendsOptedIn": false,
+        "clientFriendsOptedInUpdatedAt": 1_600_000_000,
+        "emailOptedIn": false,
+        "emailOptedInUpdatedAt": 1_600_000_000,
+        "eachEmailOptedIn": {
+            "deals": {"optedIn": false, "updatedAt": 1_600_000_000},
+            "survey": {"optedIn": false, "updatedAt": 1_600_000_000},
+        },
+        "emailVerified": true,
+        "createdAt": 1_500_000_000,
+        "updatedAt": 1_600_000_000,
+    })
+}
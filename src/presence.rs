@@ -0,0 +1,153 @@
+//! Watching friends' presence for changes
+//!
+//! [`watch_presence`] polls the friend list on an interval, and yields a
+//! [`PresenceEvent`] for every change between polls. Tokens are refreshed by
+//! the [`NsoSession`] as they expire, so a watcher can run indefinitely.
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_util::stream::{self, Stream};
+use tokio::time::{self, Interval, MissedTickBehavior};
+
+use crate::coral::{Friend, PresenceGame, PresenceState};
+use crate::{NsoError, NsoSession};
+
+/// A change in the friend list, or in a friend's presence
+///
+/// Each event carries the friend as of the poll which noticed the change
+/// (or, for [`Removed`](Self::Removed), as of the last poll they appeared
+/// in).
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PresenceEvent {
+    /// The friend was added to the friend list
+    Added(Friend),
+    /// The friend was removed from the friend list
+    Removed(Friend),
+    /// The friend came online (or woke their console)
+    CameOnline(Friend),
+    /// The friend went offline
+    WentOffline(Friend),
+    /// The friend started playing `game`
+    StartedPlaying { friend: Friend, game: PresenceGame },
+    /// The friend stopped playing `game`
+    StoppedPlaying { friend: Friend, game: PresenceGame },
+}
+
+/// The events describing the changes from `old` to `new`
+///
+/// Friends are matched by NSA ID. A friend switching games stops playing one
+/// before starting the other; a friend going offline stops playing before
+/// going offline, and one coming online comes online before starting to play.
+#[must_use]
+pub fn diff(old: &[Friend], new: &[Friend]) -> Vec<PresenceEvent> {
+    let old_by_id: HashMap<&str, &Friend> = old
+        .iter()
+        .map(|friend| (friend.nsa_id.as_str(), friend))
+        .collect();
+    let mut events = Vec::new();
+    for friend in new {
+        let Some(before) = old_by_id.get(friend.nsa_id.as_str()) else {
+            events.push(PresenceEvent::Added(friend.clone()));
+            continue;
+        };
+        let was_online = before.presence.state.is_online();
+        let is_online = friend.presence.state.is_online();
+        let was_playing = playing(before);
+        let is_playing = playing(friend);
+        let same_game = match (was_playing, is_playing) {
+            (Some(before), Some(now)) => is_same_game(before, now),
+            _ => false,
+        };
+        if !was_online && is_online {
+            events.push(PresenceEvent::CameOnline(friend.clone()));
+        }
+        if let Some(game) = was_playing.filter(|_| !same_game) {
+            events.push(PresenceEvent::StoppedPlaying {
+                friend: friend.clone(),
+                game: game.clone(),
+            });
+        }
+        if let Some(game) = is_playing.filter(|_| !same_game) {
+            events.push(PresenceEvent::StartedPlaying {
+                friend: friend.clone(),
+                game: game.clone(),
+            });
+        }
+        if was_online && !is_online {
+            events.push(PresenceEvent::WentOffline(friend.clone()));
+        }
+    }
+    let new_ids: HashSet<&str> =
+        new.iter().map(|friend| friend.nsa_id.as_str()).collect();
+    events.extend(
+        old.iter()
+            .filter(|friend| !new_ids.contains(&friend.nsa_id.as_str()))
+            .map(|friend| PresenceEvent::Removed(friend.clone())),
+    );
+    events
+}
+
+/// The game a friend is playing right now
+///
+/// Coral keeps sending the last game played after a friend stops playing,
+/// so the game only counts while their state is [`PresenceState::Playing`].
+fn playing(friend: &Friend) -> Option<&PresenceGame> {
+    match friend.presence.state {
+        PresenceState::Playing => friend.presence.game.as_ref(),
+        _ => None,
+    }
+}
+
+/// Whether two presences refer to the same game (play time aside)
+fn is_same_game(a: &PresenceGame, b: &PresenceGame) -> bool {
+    a.name == b.name && a.shop_uri == b.shop_uri
+}
+
+struct Watcher {
+    session: Arc<NsoSession>,
+    interval: Interval,
+    known: Option<Vec<Friend>>,
+    pending: VecDeque<PresenceEvent>,
+}
+
+/// Poll `session`'s friend list every `interval`, yielding every change
+///
+/// The first poll only records the current friend list; use
+/// [`NsoSession::friends`] for the friends' initial presence. A failed poll
+/// yields its error, and the next poll is compared against the last
+/// successful one, so the stream never ends on its own.
+///
+/// # Panics
+///
+/// If `interval` is zero.
+pub fn watch_presence(
+    session: Arc<NsoSession>,
+    interval: Duration,
+) -> impl Stream<Item = Result<PresenceEvent, NsoError>> {
+    let mut interval = time::interval(interval);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let watcher = Watcher {
+        session,
+        interval,
+        known: None,
+        pending: VecDeque::new(),
+    };
+    stream::unfold(watcher, |mut watcher| async move {
+        loop {
+            if let Some(event) = watcher.pending.pop_front() {
+                return Some((Ok(event), watcher));
+            }
+            watcher.interval.tick().await;
+            let friends = match watcher.session.friends().await {
+                Ok(friends) => friends,
+                Err(err) => return Some((Err(err), watcher)),
+            };
+            if let Some(known) = &watcher.known {
+                watcher.pending.extend(diff(known, &friends));
+            }
+            watcher.known = Some(friends);
+        }
+    })
+}
@@ -0,0 +1,96 @@
+//! Wrappers for the tokens issued along the login chain
+//!
+//! Each token is wrapped in its own type, so that one can't be passed where
+//! another is expected. `Debug` and `Display` print `<redacted>` instead of the
+//! token, so tokens don't end up in logs; [`expose`](SessionToken::expose)
+//! must be called to read one. Tokens are zeroed in memory when dropped.
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroize;
+
+macro_rules! secret {
+    ($(#[$meta:meta])* $name:ident) => {
+        $(#[$meta])*
+        #[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+        #[serde(transparent)]
+        pub struct $name(String);
+
+        impl $name {
+            #[must_use]
+            pub fn new(token: impl Into<String>) -> Self {
+                Self(token.into())
+            }
+
+            /// The token itself
+            #[must_use]
+            pub fn expose(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, concat!(stringify!($name), "(<redacted>)"))
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("<redacted>")
+            }
+        }
+
+        impl Drop for $name {
+            fn drop(&mut self) {
+                self.0.zeroize();
+            }
+        }
+
+        impl From<String> for $name {
+            fn from(token: String) -> Self {
+                Self(token)
+            }
+        }
+
+        impl From<&str> for $name {
+            fn from(token: &str) -> Self {
+                Self(token.to_string())
+            }
+        }
+    };
+}
+
+secret! {
+    /// The long-lived Nintendo Accounts token, from which every other token is
+    /// obtained
+    ///
+    /// Lasts for two years, so it should be stored with care.
+    SessionToken
+}
+secret! {
+    /// The Nintendo Accounts access token, used to request the user's account
+    /// information
+    AccessToken
+}
+secret! {
+    /// The Nintendo Accounts ID token, used to log in to Coral
+    IdToken
+}
+secret! {
+    /// The Coral login token (`webApiServerCredential`), used to authenticate
+    /// Coral requests
+    CoralToken
+}
+secret! {
+    /// A game's web service token (the `_gtoken` cookie)
+    GameWebToken
+}
+secret! {
+    /// The Splatoon 3 `bullet_token`, used to authenticate GraphQL queries
+    BulletToken
+}
+secret! {
+    /// The Splatoon 2 `iksm_session` cookie
+    IksmSession
+}
@@ -0,0 +1,850 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tokio::sync::Mutex;
+
+use crate::cache::{CacheLayer, CacheLock, TokenCache};
+use crate::coral::{
+    create_friend_code_url,
+    delete_friend,
+    favourite_friend,
+    get_current_user,
+    get_friend_list,
+    get_permissions,
+    get_user_by_friend_code,
+    send_friend_request,
+    unfavourite_friend,
+    update_presence_permission,
+    CurrentUser,
+    Friend,
+    FriendCode,
+    FriendCodeUrl,
+    FriendCodeUser,
+    PresencePermission,
+    UserPermissions,
+};
+use crate::f_token::Imink;
+use crate::{
+    get_access_token,
+    get_f1,
+    get_f2,
+    get_login_token,
+    get_user_info,
+    get_web_service_token,
+    get_web_services,
+    splatoon2,
+    splatoon3,
+    AccessToken,
+    BulletToken,
+    CoralToken,
+    Credentials,
+    FTokenProvider,
+    GameWebToken,
+    IdToken,
+    IksmSession,
+    JwtClaims,
+    NsoClient,
+    NsoError,
+    SessionToken,
+    StoredToken,
+    Tokens,
+    UserInfo,
+    WebService,
+    DEFAULT_SCOPES,
+};
+
+/// How long before a token's expiry it is treated as expired, so that it
+/// doesn't lapse in the middle of a request
+const EXPIRY_MARGIN: Duration = Duration::from_secs(60);
+/// How long a Splatoon 3 `bullet_token` lasts; the server doesn't say
+const BULLET_TOKEN_LIFETIME: Duration = Duration::from_secs(2 * 60 * 60);
+
+struct Expiring<T> {
+    value: T,
+    obtained_at: SystemTime,
+    expires_at: SystemTime,
+}
+
+impl<T> Expiring<T> {
+    fn new(value: T, expires_in: Duration) -> Self {
+        let now = SystemTime::now();
+        Self {
+            value,
+            obtained_at: now,
+            expires_at: now + expires_in,
+        }
+    }
+
+    fn is_fresh(&self) -> bool {
+        SystemTime::now() + EXPIRY_MARGIN < self.expires_at
+    }
+
+    fn expires_in(&self) -> Duration {
+        self.expires_at
+            .duration_since(SystemTime::now())
+            .unwrap_or_default()
+    }
+
+    fn stored(&self, token: &str) -> StoredToken {
+        let unix_seconds = |time: SystemTime| {
+            time.duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs()
+        };
+        StoredToken {
+            token: token.to_string(),
+            obtained_at: unix_seconds(self.obtained_at),
+            expires_at: Some(unix_seconds(self.expires_at)),
+        }
+    }
+}
+
+impl<T: From<String>> Expiring<T> {
+    /// Restore a stored token, if its expiry is known
+    fn restore(stored: &StoredToken) -> Option<Self> {
+        Some(Self {
+            value: stored.token.clone().into(),
+            obtained_at: stored.obtained_at(),
+            expires_at: stored.expires_at()?,
+        })
+    }
+}
+
+#[derive(Default)]
+struct State {
+    tokens: Option<Expiring<Tokens>>,
+    user_info: Option<UserInfo>,
+    login_token: Option<Expiring<CoralToken>>,
+    web_tokens: HashMap<u64, Expiring<GameWebToken>>,
+    /// The `bullet_token`, and the Splatoon 3 web token it was issued for
+    bullet_token: Option<(GameWebToken, Expiring<BulletToken>)>,
+    /// The `iksm_session`, and the Splatoon 2 web token it was issued for
+    iksm_session: Option<(GameWebToken, Expiring<IksmSession>)>,
+}
+
+/// Whether `theirs` expires later than `ours`, or `ours` doesn't exist
+fn outlasts<T, U>(theirs: &Expiring<T>, ours: Option<&Expiring<U>>) -> bool {
+    ours.is_none_or(|ours| ours.expires_at < theirs.expires_at)
+}
+
+/// Take a stored token issued for a web token (a `bullet_token` or
+/// `iksm_session`) if it outlasts the one held, assuming it was issued for the
+/// current web token
+fn restore_issued_for<T: From<String>>(
+    held: &mut Option<(GameWebToken, Expiring<T>)>,
+    web_token: Option<&Expiring<GameWebToken>>,
+    stored: Option<&StoredToken>,
+) {
+    if let (Some(web_token), Some(token)) =
+        (web_token, stored.and_then(Expiring::restore))
+    {
+        if outlasts(&token, held.as_ref().map(|(_, held)| held)) {
+            *held = Some((web_token.value.clone(), token));
+        }
+    }
+}
+
+impl State {
+    /// Take every token from `credentials` which expires later than the one
+    /// held (or which isn't held at all)
+    fn restore(&mut self, credentials: &Credentials) {
+        if let (Some(access_token), Some(id_token)) = (
+            credentials
+                .access_token
+                .as_ref()
+                .and_then(Expiring::<AccessToken>::restore),
+            credentials
+                .id_token
+                .as_ref()
+                .and_then(Expiring::<IdToken>::restore),
+        ) {
+            let expires_at = access_token.expires_at.min(id_token.expires_at);
+            if self
+                .tokens
+                .as_ref()
+                .is_none_or(|tokens| tokens.expires_at < expires_at)
+            {
+                let expires_in = expires_at
+                    .duration_since(SystemTime::now())
+                    .unwrap_or_default();
+                self.tokens = Some(Expiring {
+                    value: Tokens {
+                        access_token: access_token.value,
+                        id_token: id_token.value,
+                        expires_in: expires_in.as_secs(),
+                        token_type: "Bearer".to_string(),
+                        // The granted scopes aren't stored
+                        scope: DEFAULT_SCOPES.map(str::to_string).to_vec(),
+                    },
+                    obtained_at: access_token.obtained_at,
+                    expires_at,
+                });
+            }
+        }
+        if let Some(login_token) =
+            credentials.login_token.as_ref().and_then(Expiring::restore)
+        {
+            if outlasts(&login_token, self.login_token.as_ref()) {
+                self.login_token = Some(login_token);
+            }
+        }
+        for (game_id, web_token) in &credentials.web_tokens {
+            if let (Ok(game_id), Some(web_token)) =
+                (game_id.parse(), Expiring::restore(web_token))
+            {
+                if outlasts(&web_token, self.web_tokens.get(&game_id)) {
+                    self.web_tokens.insert(game_id, web_token);
+                }
+            }
+        }
+        restore_issued_for(
+            &mut self.bullet_token,
+            self.web_tokens.get(&splatoon3::GAME_ID),
+            credentials.bullet_token.as_ref(),
+        );
+        restore_issued_for(
+            &mut self.iksm_session,
+            self.web_tokens.get(&splatoon2::GAME_ID),
+            credentials.iksm_session.as_ref(),
+        );
+    }
+
+    fn credentials(&self, session_token: &SessionToken) -> Credentials {
+        let mut credentials = Credentials::from_session_token(session_token.expose());
+        if let Some(tokens) = &self.tokens {
+            let Tokens {
+                access_token,
+                id_token,
+                ..
+            } = &tokens.value;
+            credentials.access_token = Some(tokens.stored(access_token.expose()));
+            credentials.id_token = Some(tokens.stored(id_token.expose()));
+        }
+        credentials.login_token = self
+            .login_token
+            .as_ref()
+            .map(|login_token| login_token.stored(login_token.value.expose()));
+        for (game_id, web_token) in &self.web_tokens {
+            credentials
+                .set_web_token(*game_id, web_token.stored(web_token.value.expose()));
+        }
+        credentials.bullet_token = self
+            .bullet_token
+            .as_ref()
+            .map(|(_, bullet_token)| bullet_token.stored(bullet_token.value.expose()));
+        credentials.iksm_session = self
+            .iksm_session
+            .as_ref()
+            .map(|(_, iksm_session)| iksm_session.stored(iksm_session.value.expose()));
+        credentials
+    }
+
+    fn bullet_token_is_fresh(&self, web_token: &GameWebToken) -> bool {
+        self.bullet_token
+            .as_ref()
+            .is_some_and(|(issued_for, bullet_token)| {
+                issued_for == web_token && bullet_token.is_fresh()
+            })
+    }
+
+    fn iksm_session_is_fresh(&self, web_token: &GameWebToken) -> bool {
+        self.iksm_session
+            .as_ref()
+            .is_some_and(|(issued_for, iksm_session)| {
+                issued_for == web_token && iksm_session.is_fresh()
+            })
+    }
+}
+
+/// The Splatoon 3 tokens needed for GraphQL queries
+#[derive(Clone, Debug)]
+pub struct Splatoon3Tokens {
+    pub web_token: GameWebToken,
+    pub bullet_token: BulletToken,
+}
+
+/// A logged-in Nintendo Account, built from its `session_token`
+///
+/// Every token further down the login chain is requested lazily, cached, and
+/// requested again once it has expired.
+pub struct NsoSession {
+    session_token: SessionToken,
+    /// `None` if the `session_token` isn't a valid JWT
+    session_token_claims: Option<JwtClaims>,
+    client: NsoClient,
+    f_token_provider: Arc<dyn FTokenProvider>,
+    cache: Option<TokenCache>,
+    state: Mutex<State>,
+}
+
+impl NsoSession {
+    /// Create a session which gets its f-tokens from [`Imink`]
+    #[must_use]
+    pub fn new(session_token: impl Into<SessionToken>, client: NsoClient) -> Self {
+        let session_token = session_token.into();
+        Self {
+            session_token_claims: JwtClaims::decode(session_token.expose()).ok(),
+            session_token,
+            client,
+            f_token_provider: Arc::new(Imink),
+            cache: None,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Create a session from stored credentials, reusing any tokens which
+    /// haven't expired
+    ///
+    /// Like [`new`](Self::new), the session gets its f-tokens from [`Imink`].
+    ///
+    /// # Errors
+    ///
+    /// If the credentials don't include a `session_token`.
+    pub fn from_credentials(
+        credentials: &Credentials,
+        client: NsoClient,
+    ) -> Result<Self, NsoError> {
+        let session_token = credentials.session_token.as_ref().ok_or_else(|| {
+            NsoError::InvalidCredentials("missing session_token".to_string())
+        })?;
+        let session = Self::new(&*session_token.token, client);
+        session
+            .state
+            .try_lock()
+            .expect("session was just created")
+            .restore(credentials);
+        Ok(session)
+    }
+
+    /// Get f-tokens from `provider` instead
+    #[must_use]
+    pub fn with_f_token_provider(
+        mut self,
+        provider: impl FTokenProvider + 'static,
+    ) -> Self {
+        self.f_token_provider = Arc::new(provider);
+        self
+    }
+
+    /// Share tokens with other processes through `cache`
+    ///
+    /// Before refreshing a token, the session takes the cache's lock for it
+    /// and reuses the cached token if another process has already refreshed
+    /// it. Every token the session obtains is written back to the cache.
+    #[must_use]
+    pub fn with_cache(mut self, cache: TokenCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    #[must_use]
+    pub fn session_token(&self) -> &SessionToken {
+        &self.session_token
+    }
+
+    /// The claims of the `session_token`, if it is a valid JWT
+    #[must_use]
+    pub fn session_token_claims(&self) -> Option<&JwtClaims> {
+        self.session_token_claims.as_ref()
+    }
+
+    /// Whether the `session_token` expires within `margin`, meaning the user
+    /// will soon need to log in again
+    ///
+    /// `session_token`s last for two years. If the token's expiry is
+    /// unknown, this returns `false`.
+    #[must_use]
+    pub fn session_token_expires_within(&self, margin: Duration) -> bool {
+        self.session_token_claims
+            .as_ref()
+            .is_some_and(|claims| claims.expires_within(margin))
+    }
+
+    #[must_use]
+    pub fn client(&self) -> &NsoClient {
+        &self.client
+    }
+
+    #[must_use]
+    pub fn cache(&self) -> Option<&TokenCache> {
+        self.cache.as_ref()
+    }
+
+    /// Forget every cached token, so that the next request logs in from
+    /// scratch
+    ///
+    /// Tokens in the session's [`TokenCache`], if any, are left alone, and
+    /// are reused if they haven't expired.
+    pub async fn invalidate(&self) {
+        *self.state.lock().await = State::default();
+    }
+
+    /// Every token the session currently holds, for saving with
+    /// [`Credentials::save`]
+    ///
+    /// The user's account information isn't included; it is requested again
+    /// when needed.
+    pub async fn credentials(&self) -> Credentials {
+        self.state.lock().await.credentials(&self.session_token)
+    }
+
+    /// Get the access and ID tokens, refreshing them if they (or the
+    /// `id_token`, going by its claims) have expired
+    ///
+    /// # Errors
+    ///
+    /// If the tokens need refreshing and Nintendo rejects the request, or the
+    /// `session_token` has expired.
+    pub async fn tokens(&self) -> Result<Tokens, NsoError> {
+        let mut state = self.state.lock().await;
+        self.refresh_tokens(&mut state).await.cloned()
+    }
+
+    /// Get the user's account information, requesting it if needed
+    ///
+    /// # Errors
+    ///
+    /// If the information hasn't been requested yet and the request fails.
+    pub async fn user_info(&self) -> Result<UserInfo, NsoError> {
+        let mut state = self.state.lock().await;
+        self.refresh_user_info(&mut state).await.cloned()
+    }
+
+    /// Get the Coral login token, refreshing it (and any tokens it depends
+    /// on) if it has expired
+    ///
+    /// # Errors
+    ///
+    /// If any step of the login chain fails.
+    pub async fn login_token(&self) -> Result<CoralToken, NsoError> {
+        let mut state = self.state.lock().await;
+        self.refresh_login_token(&mut state).await.cloned()
+    }
+
+    /// Get the user's friends, along with their presence
+    ///
+    /// # Errors
+    ///
+    /// If any step of the login chain fails, or Coral responds with an error.
+    pub async fn friends(&self) -> Result<Vec<Friend>, NsoError> {
+        let client = &self.client;
+        self.with_login_token(
+            |token| async move { get_friend_list(&token, client).await },
+        )
+        .await
+    }
+
+    /// Get the user's own profile, including their friend code
+    ///
+    /// # Errors
+    ///
+    /// If any step of the login chain fails, or Coral responds with an error.
+    pub async fn current_user(&self) -> Result<CurrentUser, NsoError> {
+        let client = &self.client;
+        self.with_login_token(
+            |token| async move { get_current_user(&token, client).await },
+        )
+        .await
+    }
+
+    /// Create a link which adds the user as a friend
+    ///
+    /// # Errors
+    ///
+    /// If any step of the login chain fails, or Coral responds with an error.
+    pub async fn friend_code_url(&self) -> Result<FriendCodeUrl, NsoError> {
+        let client = &self.client;
+        self.with_login_token(|token| async move {
+            create_friend_code_url(&token, client).await
+        })
+        .await
+    }
+
+    /// Find the user with the friend code `friend_code`
+    ///
+    /// # Errors
+    ///
+    /// If any step of the login chain fails, or Coral responds with an error
+    /// (for example, if no user has the friend code).
+    pub async fn user_by_friend_code(
+        &self,
+        friend_code: &FriendCode,
+    ) -> Result<FriendCodeUser, NsoError> {
+        let client = &self.client;
+        self.with_login_token(|token| async move {
+            get_user_by_friend_code(friend_code, &token, client).await
+        })
+        .await
+    }
+
+    /// Send a friend request to the user with the Nintendo Switch account ID
+    /// `nsa_id`
+    ///
+    /// # Errors
+    ///
+    /// If any step of the login chain fails, or Coral responds with an error.
+    pub async fn send_friend_request(&self, nsa_id: &str) -> Result<(), NsoError> {
+        let client = &self.client;
+        self.with_login_token(|token| async move {
+            send_friend_request(nsa_id, &token, client).await
+        })
+        .await
+    }
+
+    /// Mark the friend with the Nintendo Switch account ID `nsa_id` as a best
+    /// friend
+    ///
+    /// # Errors
+    ///
+    /// If any step of the login chain fails, or Coral responds with an error.
+    pub async fn favourite_friend(&self, nsa_id: &str) -> Result<(), NsoError> {
+        let client = &self.client;
+        self.with_login_token(|token| async move {
+            favourite_friend(nsa_id, &token, client).await
+        })
+        .await
+    }
+
+    /// Stop marking the friend with the Nintendo Switch account ID `nsa_id`
+    /// as a best friend
+    ///
+    /// # Errors
+    ///
+    /// If any step of the login chain fails, or Coral responds with an error.
+    pub async fn unfavourite_friend(&self, nsa_id: &str) -> Result<(), NsoError> {
+        let client = &self.client;
+        self.with_login_token(|token| async move {
+            unfavourite_friend(nsa_id, &token, client).await
+        })
+        .await
+    }
+
+    /// Remove the friend with the Nintendo Switch account ID `nsa_id`
+    ///
+    /// # Errors
+    ///
+    /// If any step of the login chain fails, or Coral responds with an error.
+    pub async fn delete_friend(&self, nsa_id: &str) -> Result<(), NsoError> {
+        let client = &self.client;
+        self.with_login_token(|token| async move {
+            delete_friend(nsa_id, &token, client).await
+        })
+        .await
+    }
+
+    /// Get who can see the user's presence
+    ///
+    /// # Errors
+    ///
+    /// If any step of the login chain fails, or Coral responds with an error.
+    pub async fn permissions(&self) -> Result<UserPermissions, NsoError> {
+        let client = &self.client;
+        self.with_login_token(
+            |token| async move { get_permissions(&token, client).await },
+        )
+        .await
+    }
+
+    /// Change who can see the user's presence
+    ///
+    /// The current permissions are fetched first for their etag, so the
+    /// change applies on top of whatever they are now.
+    ///
+    /// # Errors
+    ///
+    /// If any step of the login chain fails, or Coral responds with an error.
+    pub async fn set_presence_permission(
+        &self,
+        presence: PresencePermission,
+    ) -> Result<(), NsoError> {
+        let client = &self.client;
+        self.with_login_token(|token| async move {
+            let permissions = get_permissions(&token, client).await?;
+            update_presence_permission(presence, &permissions.etag, &token, client)
+                .await
+        })
+        .await
+    }
+
+    /// Get the web token for a game, refreshing it (and any tokens it depends
+    /// on) if it has expired
+    ///
+    /// # Errors
+    ///
+    /// If any step of the login chain fails.
+    pub async fn game_web_token<const GAME_ID: u64>(
+        &self,
+    ) -> Result<GameWebToken, NsoError> {
+        self.web_service_token(GAME_ID).await
+    }
+
+    /// Get the web token for the web service `service_id` (see
+    /// [`WebService::id`]), refreshing it (and any tokens it depends on) if it
+    /// has expired
+    ///
+    /// # Errors
+    ///
+    /// If any step of the login chain fails.
+    pub async fn web_service_token(
+        &self,
+        service_id: u64,
+    ) -> Result<GameWebToken, NsoError> {
+        let mut state = self.state.lock().await;
+        self.refresh_web_token(service_id, &mut state)
+            .await
+            .cloned()
+    }
+
+    /// List the web services available to the user
+    ///
+    /// # Errors
+    ///
+    /// If any step of the login chain fails, or Coral responds with an error.
+    pub async fn web_services(&self) -> Result<Vec<WebService>, NsoError> {
+        let client = &self.client;
+        self.with_login_token(
+            |token| async move { get_web_services(&token, client).await },
+        )
+        .await
+    }
+
+    /// Get the Splatoon 3 web token and `bullet_token`, refreshing them (and
+    /// any tokens they depend on) if they have expired
+    ///
+    /// # Errors
+    ///
+    /// If any step of the login chain fails.
+    pub async fn splatoon3_tokens(&self) -> Result<Splatoon3Tokens, NsoError> {
+        let mut state = self.state.lock().await;
+        let mut web_token = self
+            .refresh_web_token(splatoon3::GAME_ID, &mut state)
+            .await?
+            .clone();
+        if !state.bullet_token_is_fresh(&web_token) {
+            let _lock = self.lock_cache(CacheLayer::BulletToken, &mut state).await?;
+            // Another process may have refreshed the web token too
+            web_token = state.web_tokens[&splatoon3::GAME_ID].value.clone();
+            if !state.bullet_token_is_fresh(&web_token) {
+                let bullet_token =
+                    splatoon3::get_bullet_token(&web_token, &self.client).await?;
+                let lifetime = state.web_tokens[&splatoon3::GAME_ID]
+                    .expires_in()
+                    .min(BULLET_TOKEN_LIFETIME);
+                state.bullet_token =
+                    Some((web_token.clone(), Expiring::new(bullet_token, lifetime)));
+                self.store_cache(&state).await?;
+            }
+        }
+        let (_, bullet_token) = state
+            .bullet_token
+            .as_ref()
+            .expect("bullet token was just refreshed");
+        Ok(Splatoon3Tokens {
+            bullet_token: bullet_token.value.clone(),
+            web_token,
+        })
+    }
+
+    /// Get the Splatoon 2 `iksm_session`, requesting it again whenever the
+    /// Splatoon 2 web token is refreshed
+    ///
+    /// # Errors
+    ///
+    /// If any step of the login chain fails.
+    pub async fn splatoon2_iksm_session(&self) -> Result<IksmSession, NsoError> {
+        let mut state = self.state.lock().await;
+        let mut web_token = self
+            .refresh_web_token(splatoon2::GAME_ID, &mut state)
+            .await?
+            .clone();
+        if !state.iksm_session_is_fresh(&web_token) {
+            let _lock = self.lock_cache(CacheLayer::IksmSession, &mut state).await?;
+            // Another process may have refreshed the web token too
+            web_token = state.web_tokens[&splatoon2::GAME_ID].value.clone();
+            if !state.iksm_session_is_fresh(&web_token) {
+                let iksm_session =
+                    splatoon2::get_iksm_session(&web_token, &self.client).await?;
+                let lifetime = state.web_tokens[&splatoon2::GAME_ID].expires_in();
+                state.iksm_session =
+                    Some((web_token, Expiring::new(iksm_session, lifetime)));
+                self.store_cache(&state).await?;
+            }
+        }
+        let (_, iksm_session) = state
+            .iksm_session
+            .as_ref()
+            .expect("iksm_session was just refreshed");
+        Ok(iksm_session.value.clone())
+    }
+
+    /// Call Coral with the login token, refreshing it and retrying once if
+    /// Coral rejects it before it was due to expire
+    async fn with_login_token<T, F, Fut>(&self, call: F) -> Result<T, NsoError>
+    where
+        F: Fn(CoralToken) -> Fut,
+        Fut: Future<Output = Result<T, NsoError>>,
+    {
+        let login_token = self.login_token().await?;
+        match call(login_token.clone()).await {
+            Err(NsoError::Coral(err)) if err.code.requires_relogin() => {
+                self.forget_login_token(&login_token).await;
+                call(self.login_token().await?).await
+            },
+            result => result,
+        }
+    }
+
+    /// Forget the login token if it is still `stale`, so that the next
+    /// request refreshes it
+    ///
+    /// The token is reused if it is still in the session's [`TokenCache`]
+    /// and hasn't expired there, as with [`invalidate`](Self::invalidate).
+    async fn forget_login_token(&self, stale: &CoralToken) {
+        let mut state = self.state.lock().await;
+        if state
+            .login_token
+            .as_ref()
+            .is_some_and(|login_token| login_token.value == *stale)
+        {
+            state.login_token = None;
+        }
+    }
+
+    /// Take the cache's lock for `layer`, and pick up any tokens other
+    /// processes have written
+    ///
+    /// The lock must be held until the refreshed token has been
+    /// [stored](Self::store_cache).
+    async fn lock_cache(
+        &self,
+        layer: CacheLayer,
+        state: &mut State,
+    ) -> Result<Option<CacheLock>, NsoError> {
+        let Some(cache) = &self.cache else {
+            return Ok(None);
+        };
+        let lock = cache.lock(layer).await?;
+        state.restore(&cache.load().await?);
+        Ok(Some(lock))
+    }
+
+    async fn store_cache(&self, state: &State) -> Result<(), NsoError> {
+        match &self.cache {
+            Some(cache) => cache.store(state.credentials(&self.session_token)).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn refresh_tokens<'a>(
+        &self,
+        state: &'a mut State,
+    ) -> Result<&'a Tokens, NsoError> {
+        if !state.tokens.as_ref().is_some_and(Expiring::is_fresh) {
+            let _lock = self.lock_cache(CacheLayer::Tokens, state).await?;
+            if !state.tokens.as_ref().is_some_and(Expiring::is_fresh) {
+                let claims = self.session_token_claims.as_ref();
+                if claims.is_some_and(JwtClaims::is_expired) {
+                    return Err(NsoError::SessionTokenExpired);
+                }
+                let tokens =
+                    get_access_token(&self.session_token, &self.client).await?;
+                let mut expires_in = Duration::from_secs(tokens.expires_in);
+                if let Ok(claims) = tokens.id_token_claims() {
+                    expires_in = expires_in.min(
+                        claims
+                            .expires_at()
+                            .duration_since(SystemTime::now())
+                            .unwrap_or_default(),
+                    );
+                }
+                state.tokens = Some(Expiring::new(tokens, expires_in));
+                self.store_cache(state).await?;
+            }
+        }
+        Ok(&state
+            .tokens
+            .as_ref()
+            .expect("tokens were just refreshed")
+            .value)
+    }
+
+    async fn refresh_user_info<'a>(
+        &self,
+        state: &'a mut State,
+    ) -> Result<&'a UserInfo, NsoError> {
+        if state.user_info.is_none() {
+            let access_token = self.refresh_tokens(state).await?.access_token.clone();
+            state.user_info = Some(get_user_info(&access_token, &self.client).await?);
+        }
+        Ok(state
+            .user_info
+            .as_ref()
+            .expect("user info was just requested"))
+    }
+
+    async fn refresh_login_token<'a>(
+        &self,
+        state: &'a mut State,
+    ) -> Result<&'a CoralToken, NsoError> {
+        if !state.login_token.as_ref().is_some_and(Expiring::is_fresh) {
+            let _lock = self.lock_cache(CacheLayer::LoginToken, state).await?;
+            if !state.login_token.as_ref().is_some_and(Expiring::is_fresh) {
+                let user_info = self.refresh_user_info(state).await?.clone();
+                let id_token = self.refresh_tokens(state).await?.id_token.clone();
+                let f1 =
+                    get_f1(&*self.f_token_provider, &id_token, &self.client).await?;
+                let login_token =
+                    get_login_token(&f1, &id_token, &user_info, &self.client).await?;
+                state.login_token = Some(Expiring::new(
+                    login_token.access_token,
+                    Duration::from_secs(login_token.expires_in),
+                ));
+                self.store_cache(state).await?;
+            }
+        }
+        Ok(&state
+            .login_token
+            .as_ref()
+            .expect("login token was just refreshed")
+            .value)
+    }
+
+    async fn refresh_web_token<'a>(
+        &self,
+        service_id: u64,
+        state: &'a mut State,
+    ) -> Result<&'a GameWebToken, NsoError> {
+        if !state
+            .web_tokens
+            .get(&service_id)
+            .is_some_and(Expiring::is_fresh)
+        {
+            let _lock = self
+                .lock_cache(CacheLayer::WebToken(service_id), state)
+                .await?;
+            if !state
+                .web_tokens
+                .get(&service_id)
+                .is_some_and(Expiring::is_fresh)
+            {
+                let login_token = self.refresh_login_token(state).await?.clone();
+                let f2 =
+                    get_f2(&*self.f_token_provider, &login_token, &self.client).await?;
+                let web_token =
+                    get_web_service_token(service_id, &f2, &login_token, &self.client)
+                        .await?;
+                state.web_tokens.insert(
+                    service_id,
+                    Expiring::new(
+                        web_token.access_token,
+                        Duration::from_secs(web_token.expires_in),
+                    ),
+                );
+                self.store_cache(state).await?;
+            }
+        }
+        Ok(&state.web_tokens[&service_id].value)
+    }
+}
@@ -0,0 +1,210 @@
+use std::time::{Duration, SystemTime};
+
+use reqwest::header::RETRY_AFTER;
+use reqwest::{RequestBuilder, Response};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::cassette::execute;
+use crate::{NsoClient, NsoError};
+
+/// Send a request through the client's transport (see
+/// [`cassette`](crate::cassette)), recording it in a `tracing` span named
+/// after the login chain `step` if the `tracing` feature is enabled
+///
+/// Only the method, host, path, status and latency are traced. Headers (and
+/// so every token and cookie), query strings and bodies never are.
+pub(crate) async fn send(
+    step: &'static str,
+    request: RequestBuilder,
+    client: &NsoClient,
+) -> Result<Response, NsoError> {
+    let request = request.build()?;
+    #[cfg(feature = "tracing")]
+    {
+        use std::time::Instant;
+
+        use tracing::field::Empty;
+        use tracing::Instrument;
+
+        let url = request.url();
+        let span = tracing::info_span!(
+            "nso_request",
+            step,
+            method = %request.method(),
+            host = url.host_str().unwrap_or_default(),
+            path = url.path(),
+            status = Empty,
+            latency_ms = Empty,
+        );
+        let start = Instant::now();
+        let result = execute(request, client).instrument(span.clone()).await;
+        span.record(
+            "latency_ms",
+            u64::try_from(start.elapsed().as_millis()).unwrap_or(u64::MAX),
+        );
+        match &result {
+            Ok(response) => {
+                span.record("status", response.status().as_u16());
+                tracing::debug!(parent: &span, "request completed");
+            },
+            Err(err) => {
+                tracing::warn!(parent: &span, error = %err, "request failed");
+            },
+        }
+        result
+    }
+    #[cfg(not(feature = "tracing"))]
+    {
+        let _ = step;
+        execute(request, client).await
+    }
+}
+
+/// Turn an unsuccessful HTTP status into an [`NsoError::Status`], attaching
+/// the response body
+pub(crate) async fn check_status(response: Response) -> Result<Response, NsoError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let url = response.url().to_string();
+    let retry_after = retry_after(&response);
+    let body = response.text().await?;
+    Err(NsoError::Status {
+        url,
+        status,
+        body,
+        retry_after,
+    })
+}
+
+/// How long a response's `Retry-After` header (either a number of seconds or
+/// an HTTP date) asks to wait, if it has one
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    match value.parse() {
+        Ok(seconds) => Some(Duration::from_secs(seconds)),
+        Err(_) => Some(
+            httpdate::parse_http_date(value)
+                .ok()?
+                .duration_since(SystemTime::now())
+                .unwrap_or_default(),
+        ),
+    }
+}
+
+/// Deserialise a response body, keeping the body around if it doesn't match
+/// the expected shape
+pub(crate) fn parse<T: DeserializeOwned>(body: String) -> Result<T, NsoError> {
+    serde_json::from_str(&body).map_err(|source| NsoError::Deserialize { source, body })
+}
+
+/// Check the status of a response and deserialise its body
+pub(crate) async fn json<T: DeserializeOwned>(
+    response: Response,
+) -> Result<T, NsoError> {
+    parse(check_status(response).await?.text().await?)
+}
+
+/// Like [`json`], but recognises the OAuth-style error payloads returned by
+/// Nintendo Accounts
+pub(crate) async fn accounts_json<T: DeserializeOwned>(
+    response: Response,
+) -> Result<T, NsoError> {
+    #[derive(Deserialize)]
+    struct AccountsError {
+        error: String,
+        #[serde(default)]
+        error_description: String,
+    }
+    match json(response).await {
+        Err(NsoError::Status {
+            url,
+            status,
+            body,
+            retry_after,
+        }) => match serde_json::from_str::<AccountsError>(&body) {
+            Ok(err) => Err(NsoError::Accounts {
+                error: err.error,
+                description: err.error_description,
+            }),
+            Err(_) => Err(NsoError::Status {
+                url,
+                status,
+                body,
+                retry_after,
+            }),
+        },
+        rv => rv,
+    }
+}
+
+/// Like [`json`], but recognises the error payloads returned by f-token
+/// providers (imink's `{"error": true, "reason": ...}`, and nxapi-znca-api's
+/// `{"error": ..., "error_message": ...}`)
+pub(crate) async fn f_token_json<T: DeserializeOwned>(
+    response: Response,
+) -> Result<T, NsoError> {
+    #[derive(Deserialize)]
+    struct FTokenError {
+        #[serde(alias = "error_message")]
+        reason: String,
+    }
+    match json(response).await {
+        Err(NsoError::Status {
+            url,
+            status,
+            body,
+            retry_after,
+        }) => match serde_json::from_str::<FTokenError>(&body) {
+            Ok(err) => Err(NsoError::FToken {
+                status,
+                reason: err.reason,
+                retry_after,
+            }),
+            Err(_) => Err(NsoError::Status {
+                url,
+                status,
+                body,
+                retry_after,
+            }),
+        },
+        rv => rv,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(retry_after: &str) -> Response {
+        http::Response::builder()
+            .header(RETRY_AFTER, retry_after)
+            .body("")
+            .expect("response is valid")
+            .into()
+    }
+
+    #[test]
+    fn retry_after_seconds_are_read() {
+        assert_eq!(
+            retry_after(&response("120")),
+            Some(Duration::from_secs(120))
+        );
+    }
+
+    #[test]
+    fn retry_after_dates_are_read() {
+        let date = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(60));
+        let wait = retry_after(&response(&date)).expect("date was not read");
+        assert!(wait > Duration::from_secs(55) && wait <= Duration::from_secs(60));
+        let past = httpdate::fmt_http_date(SystemTime::UNIX_EPOCH);
+        assert_eq!(retry_after(&response(&past)), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn malformed_retry_after_is_ignored() {
+        assert_eq!(retry_after(&response("soon")), None);
+    }
+}
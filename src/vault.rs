@@ -0,0 +1,348 @@
+//! Encrypting stored credentials with a passphrase
+//!
+//! A vault is a JSON file holding [`Credentials`] encrypted with
+//! XChaCha20-Poly1305, under a key derived from the passphrase with Argon2id.
+//! The KDF parameters and salt are stored alongside the ciphertext, so a vault
+//! can be unlocked with nothing but its passphrase.
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::STANDARD;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaChaRng;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::credentials::write_private;
+use crate::{Credentials, NsoError};
+
+/// The version of the vault format written by this version of the crate
+pub const VAULT_VERSION: u32 = 1;
+/// Bound to the ciphertext, so that a vault can't be mistaken for a future
+/// format with the same key
+const ASSOCIATED_DATA: &[u8] = b"nso-vault-v1";
+const SALT_LEN: usize = 16;
+const KEY_LEN: usize = 32;
+
+/// How expensive it is to derive the key from the passphrase
+///
+/// These are Argon2id's parameters; see
+/// [RFC 9106](https://www.rfc-editor.org/rfc/rfc9106) for guidance. Vaults
+/// are only created or unlocked with parameters up to the `MAX_*` limits, so
+/// a crafted vault can't make unlocking exhaust memory or run indefinitely.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    /// Memory used, in KiB
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for KdfParams {
+    /// 64 MiB and three passes, which takes well under a second on most
+    /// machines
+    fn default() -> Self {
+        Self {
+            memory_kib: 64 * 1024,
+            iterations: 3,
+            parallelism: 1,
+        }
+    }
+}
+
+impl KdfParams {
+    /// The most memory a vault may use, in KiB (1 GiB)
+    pub const MAX_MEMORY_KIB: u32 = 1024 * 1024;
+    pub const MAX_ITERATIONS: u32 = 32;
+    pub const MAX_PARALLELISM: u32 = 16;
+
+    /// Check the parameters are within the `MAX_*` limits, and acceptable to
+    /// Argon2
+    fn check(&self) -> Result<Params, NsoError> {
+        let out_of_range = |name: &str, value: u32, max: u32| {
+            NsoError::InvalidVault(format!("{name} {value} is not in 1..={max}"))
+        };
+        if !(1..=Self::MAX_MEMORY_KIB).contains(&self.memory_kib) {
+            return Err(out_of_range(
+                "memory_kib",
+                self.memory_kib,
+                Self::MAX_MEMORY_KIB,
+            ));
+        }
+        if !(1..=Self::MAX_ITERATIONS).contains(&self.iterations) {
+            return Err(out_of_range(
+                "iterations",
+                self.iterations,
+                Self::MAX_ITERATIONS,
+            ));
+        }
+        if !(1..=Self::MAX_PARALLELISM).contains(&self.parallelism) {
+            return Err(out_of_range(
+                "parallelism",
+                self.parallelism,
+                Self::MAX_PARALLELISM,
+            ));
+        }
+        Params::new(
+            self.memory_kib,
+            self.iterations,
+            self.parallelism,
+            Some(KEY_LEN),
+        )
+        .map_err(|err| NsoError::InvalidVault(err.to_string()))
+    }
+
+    fn derive_key(
+        &self,
+        passphrase: &str,
+        salt: &[u8],
+    ) -> Result<Zeroizing<[u8; KEY_LEN]>, NsoError> {
+        let params = self.check()?;
+        let mut key = Zeroizing::new([0; KEY_LEN]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), salt, &mut *key)
+            .map_err(|err| NsoError::InvalidVault(err.to_string()))?;
+        Ok(key)
+    }
+}
+
+/// The on-disk format of a vault
+#[derive(Serialize, Deserialize)]
+struct VaultFile {
+    version: u32,
+    kdf: KdfParams,
+    #[serde(with = "base64_bytes")]
+    salt: Vec<u8>,
+    #[serde(with = "base64_bytes")]
+    nonce: Vec<u8>,
+    #[serde(with = "base64_bytes")]
+    ciphertext: Vec<u8>,
+}
+
+mod base64_bytes {
+    use super::{Deserialize, Deserializer, Serializer, STANDARD};
+
+    pub fn serialize<S: Serializer>(
+        bytes: &[u8],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&base64::encode_config(bytes, STANDARD))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        base64::decode_config(encoded, STANDARD).map_err(serde::de::Error::custom)
+    }
+}
+
+/// A locked vault: credentials encrypted at rest
+///
+/// Nothing can be read from a locked vault until it is
+/// [unlocked](Self::unlock) with its passphrase.
+pub struct Vault {
+    path: PathBuf,
+    file: VaultFile,
+}
+
+impl Vault {
+    /// Open the vault at `path`, without unlocking it
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be read, [`NsoError::InvalidVault`] if it is not a
+    /// vault or its KDF parameters are out of range, or
+    /// [`NsoError::UnsupportedVaultVersion`] if it was written by a newer
+    /// version of the crate.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, NsoError> {
+        let path = path.into();
+        let file: VaultFile = serde_json::from_str(&fs::read_to_string(&path)?)
+            .map_err(|err| NsoError::InvalidVault(err.to_string()))?;
+        if file.version > VAULT_VERSION {
+            return Err(NsoError::UnsupportedVaultVersion(file.version));
+        }
+        file.kdf.check()?;
+        if file.nonce.len() != 24 {
+            return Err(NsoError::InvalidVault(
+                "vault nonce is not 24 bytes".to_string(),
+            ));
+        }
+        Ok(Self { path, file })
+    }
+
+    /// Create a vault at `path` holding `credentials`, encrypted with
+    /// `passphrase`, and leave it unlocked
+    ///
+    /// Any existing file at `path` is replaced.
+    ///
+    /// # Errors
+    ///
+    /// [`NsoError::InvalidVault`] if `kdf` is out of range, or an error if the
+    /// file cannot be written.
+    pub fn create(
+        path: impl Into<PathBuf>,
+        passphrase: &str,
+        kdf: KdfParams,
+        credentials: Credentials,
+    ) -> Result<UnlockedVault, NsoError> {
+        let mut salt = vec![0; SALT_LEN];
+        ChaChaRng::from_entropy().fill(&mut salt[..]);
+        let key = kdf.derive_key(passphrase, &salt)?;
+        let vault = UnlockedVault {
+            path: path.into(),
+            kdf,
+            salt,
+            key,
+            credentials,
+        };
+        vault.save()?;
+        Ok(vault)
+    }
+
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Decrypt the vault with `passphrase`
+    ///
+    /// # Errors
+    ///
+    /// [`NsoError::IncorrectPassphrase`] if the passphrase is wrong (or the
+    /// ciphertext has been tampered with), [`NsoError::InvalidVault`] if the
+    /// decrypted contents aren't text, or an error if the decrypted
+    /// credentials cannot be parsed.
+    pub fn unlock(self, passphrase: &str) -> Result<UnlockedVault, NsoError> {
+        let key = self.file.kdf.derive_key(passphrase, &self.file.salt)?;
+        let plaintext = Zeroizing::new(
+            XChaCha20Poly1305::new(key.as_slice().into())
+                .decrypt(
+                    XNonce::from_slice(&self.file.nonce),
+                    Payload {
+                        msg: &self.file.ciphertext,
+                        aad: ASSOCIATED_DATA,
+                    },
+                )
+                .map_err(|_| NsoError::IncorrectPassphrase)?,
+        );
+        let plaintext = std::str::from_utf8(&plaintext)
+            .map_err(|err| NsoError::InvalidVault(err.to_string()))?;
+        Ok(UnlockedVault {
+            path: self.path,
+            kdf: self.file.kdf,
+            salt: self.file.salt,
+            key,
+            credentials: Credentials::from_json(plaintext)?,
+        })
+    }
+}
+
+/// An unlocked vault, holding the decrypted credentials and the key derived
+/// from the passphrase
+///
+/// The key and the credentials are zeroed when the vault is
+/// [locked](Self::lock) or dropped.
+/// Changes to the credentials are only written back by [`save`](Self::save)
+/// or [`lock`](Self::lock).
+pub struct UnlockedVault {
+    path: PathBuf,
+    kdf: KdfParams,
+    salt: Vec<u8>,
+    key: Zeroizing<[u8; KEY_LEN]>,
+    credentials: Credentials,
+}
+
+impl UnlockedVault {
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    #[must_use]
+    pub fn credentials(&self) -> &Credentials {
+        &self.credentials
+    }
+
+    pub fn credentials_mut(&mut self) -> &mut Credentials {
+        &mut self.credentials
+    }
+
+    /// Replace the stored credentials (for example, with
+    /// [`NsoSession::credentials`](crate::NsoSession::credentials))
+    pub fn set_credentials(&mut self, credentials: Credentials) {
+        self.credentials = credentials;
+    }
+
+    /// Re-encrypt the credentials with a new passphrase
+    ///
+    /// The vault is not written until the next [`save`](Self::save).
+    ///
+    /// # Errors
+    ///
+    /// If the key cannot be derived, in which case the passphrase is
+    /// unchanged.
+    pub fn change_passphrase(&mut self, passphrase: &str) -> Result<(), NsoError> {
+        let mut salt = vec![0; SALT_LEN];
+        ChaChaRng::from_entropy().fill(&mut salt[..]);
+        // Only replace the salt once a key has been derived from it, so that
+        // a failure leaves the vault as it was
+        self.key = self.kdf.derive_key(passphrase, &salt)?;
+        self.salt = salt;
+        Ok(())
+    }
+
+    /// Encrypt the credentials and write them to the vault's file
+    ///
+    /// The file is replaced atomically, so a crash mid-write can't corrupt it.
+    /// On Unix, it is only readable by the user.
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be written.
+    pub fn save(&self) -> Result<(), NsoError> {
+        self.write().map(drop)
+    }
+
+    /// Save the credentials, then forget them and the key
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be written.
+    pub fn lock(self) -> Result<Vault, NsoError> {
+        let file = self.write()?;
+        Ok(Vault {
+            path: self.path.clone(),
+            file,
+        })
+    }
+
+    fn write(&self) -> Result<VaultFile, NsoError> {
+        let mut nonce = vec![0; 24];
+        ChaChaRng::from_entropy().fill(&mut nonce[..]);
+        let mut plaintext = self.credentials.to_json()?;
+        let ciphertext = XChaCha20Poly1305::new(self.key.as_slice().into()).encrypt(
+            XNonce::from_slice(&nonce),
+            Payload {
+                msg: plaintext.as_bytes(),
+                aad: ASSOCIATED_DATA,
+            },
+        );
+        plaintext.zeroize();
+        let file = VaultFile {
+            version: VAULT_VERSION,
+            kdf: self.kdf.clone(),
+            salt: self.salt.clone(),
+            nonce,
+            ciphertext: ciphertext
+                .map_err(|err| NsoError::InvalidVault(err.to_string()))?,
+        };
+        let contents = serde_json::to_string_pretty(&file)
+            .map_err(|err| NsoError::InvalidVault(err.to_string()))?;
+        write_private(&self.path, contents.as_bytes())?;
+        Ok(file)
+    }
+}
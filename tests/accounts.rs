@@ -0,0 +1,120 @@
+#![cfg(feature = "mock")]
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use common::TempPath;
+use nso::mock::{fixtures, MockServer};
+use nso::{AccountRegistry, NsoError};
+use reqwest::Client;
+
+#[tokio::test]
+async fn accounts_are_found_by_nickname_or_id() {
+    let server = MockServer::start()
+        .await
+        .expect("Failed to start mock server");
+    let mut registry = AccountRegistry::new().with_client(server.client());
+    let account = registry
+        .add(fixtures::SESSION_TOKEN)
+        .await
+        .expect("Failed to add account");
+    assert_eq!(account.id, fixtures::NA_ID);
+    let by_nickname = registry
+        .session(fixtures::NICKNAME)
+        .expect("Failed to find account by nickname");
+    let by_id = registry
+        .session(fixtures::NA_ID)
+        .expect("Failed to find account by ID");
+    assert!(Arc::ptr_eq(&by_nickname, &by_id));
+    assert!(matches!(
+        registry.find("Nobody"),
+        Err(NsoError::UnknownAccount(_)),
+    ));
+}
+
+#[tokio::test]
+async fn accounts_are_saved_and_loaded() {
+    let server = MockServer::start()
+        .await
+        .expect("Failed to start mock server");
+    let path = TempPath::new("accounts.json");
+    let mut registry = AccountRegistry::new().with_client(server.client());
+    registry
+        .add(fixtures::SESSION_TOKEN)
+        .await
+        .expect("Failed to add account");
+    registry.save(&path).await.expect("Failed to save accounts");
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let metadata = std::fs::metadata(&path).expect("Accounts not saved");
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+    }
+    let loaded = AccountRegistry::new()
+        .load(&path)
+        .expect("Failed to load accounts");
+    assert_eq!(
+        loaded
+            .find(fixtures::NICKNAME)
+            .map(|account| &account.id)
+            .ok(),
+        Some(&fixtures::NA_ID.to_string()),
+    );
+}
+
+#[tokio::test]
+async fn account_clients_are_built_by_the_http_builder() {
+    let server = MockServer::start()
+        .await
+        .expect("Failed to start mock server");
+    let built = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&built);
+    let mut registry = AccountRegistry::new()
+        .with_client(server.client())
+        .with_http_builder(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Client::builder().user_agent("nso-test")
+        });
+    registry
+        .add(fixtures::SESSION_TOKEN)
+        .await
+        .expect("Failed to add account");
+    assert_eq!(built.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn newer_registries_are_rejected() {
+    let path = TempPath::new("newer-accounts.json");
+    std::fs::write(&path, r#"{"version": 99, "accounts": []}"#)
+        .expect("Failed to write accounts");
+    let loaded = AccountRegistry::new().load(&path);
+    assert!(matches!(
+        loaded,
+        Err(NsoError::UnsupportedRegistryVersion(99)),
+    ));
+}
+
+#[test]
+fn account_credentials_are_checked_when_loaded() {
+    let path = TempPath::new("newer-credentials.json");
+    std::fs::write(
+        &path,
+        format!(
+            r#"{{"version": 1, "accounts": [{{
+                "id": "{}",
+                "nickname": "{}",
+                "credentials": {{"version": 99}}
+            }}]}}"#,
+            fixtures::NA_ID,
+            fixtures::NICKNAME,
+        ),
+    )
+    .expect("Failed to write accounts");
+    let loaded = AccountRegistry::new().load(&path);
+    assert!(matches!(
+        loaded,
+        Err(NsoError::UnsupportedCredentialsVersion(99)),
+    ));
+}
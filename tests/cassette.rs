@@ -0,0 +1,126 @@
+#![cfg(feature = "mock")]
+mod common;
+
+use common::TempPath;
+use nso::cassette::{Cassette, Recorder};
+use nso::mock::{fixtures, MockFailure, MockRoute, MockServer};
+use nso::{NsoError, NsoSession};
+
+async fn record(server: &MockServer) -> Cassette {
+    let recording = server.client().with_recorder(Recorder::new());
+    NsoSession::new(fixtures::SESSION_TOKEN, recording.clone())
+        .splatoon3_tokens()
+        .await
+        .expect("Failed to get Splatoon 3 tokens");
+    recording
+        .recorder()
+        .expect("Client has no recorder")
+        .cassette()
+}
+
+#[tokio::test]
+async fn cassettes_hold_no_secrets() {
+    let server = MockServer::start()
+        .await
+        .expect("Failed to start mock server");
+    let recorded = serde_json::to_string(&record(&server).await)
+        .expect("Failed to serialise cassette");
+    for secret in [
+        fixtures::SESSION_TOKEN,
+        fixtures::ACCESS_TOKEN,
+        fixtures::LOGIN_TOKEN,
+        fixtures::WEB_TOKEN,
+        fixtures::BULLET_TOKEN,
+    ] {
+        assert!(!recorded.contains(secret), "Cassette contains a secret");
+    }
+}
+
+#[tokio::test]
+async fn cassettes_replay_without_a_server() {
+    let server = MockServer::start()
+        .await
+        .expect("Failed to start mock server");
+    let cassette = record(&server).await;
+    let hits = server.hits(MockRoute::BulletToken);
+    NsoSession::new(
+        fixtures::SESSION_TOKEN,
+        server.client().with_replay(cassette),
+    )
+    .splatoon3_tokens()
+    .await
+    .expect("Failed to replay Splatoon 3 tokens");
+    assert_eq!(server.hits(MockRoute::BulletToken), hits);
+}
+
+#[tokio::test]
+async fn unrecorded_requests_miss() {
+    let server = MockServer::start()
+        .await
+        .expect("Failed to start mock server");
+    let replaying = server.client().with_replay(record(&server).await);
+    NsoSession::new(fixtures::SESSION_TOKEN, replaying.clone())
+        .splatoon3_tokens()
+        .await
+        .expect("Failed to replay Splatoon 3 tokens");
+    assert!(matches!(
+        NsoSession::new(fixtures::SESSION_TOKEN, replaying)
+            .user_info()
+            .await,
+        Err(NsoError::ReplayMiss { .. }),
+    ));
+}
+
+#[tokio::test]
+async fn replayed_errors_report_the_requested_url() {
+    let server = MockServer::start()
+        .await
+        .expect("Failed to start mock server");
+    let recording = server.client().with_recorder(Recorder::new());
+    server.fail_times(MockRoute::UserInfo, MockFailure::Status(404), 1);
+    assert!(NsoSession::new(fixtures::SESSION_TOKEN, recording.clone())
+        .user_info()
+        .await
+        .is_err());
+    let cassette = recording
+        .recorder()
+        .expect("Client has no recorder")
+        .cassette();
+    match NsoSession::new(
+        fixtures::SESSION_TOKEN,
+        server.client().with_replay(cassette),
+    )
+    .user_info()
+    .await
+    {
+        Err(NsoError::Status { url, .. }) => {
+            assert_eq!(
+                url,
+                format!("{}/2.0.0/users/me", server.endpoints().accounts_api)
+            );
+        },
+        Err(err) => panic!("Unexpected error: {err}"),
+        Ok(_) => panic!("Recorded failure was not replayed"),
+    }
+}
+
+#[tokio::test]
+async fn cassettes_are_saved_privately() {
+    let server = MockServer::start()
+        .await
+        .expect("Failed to start mock server");
+    let path = TempPath::new("cassette.json");
+    record(&server)
+        .await
+        .save(&path)
+        .expect("Failed to save cassette");
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let metadata = std::fs::metadata(&path).expect("Cassette not saved");
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+    }
+    let loaded = Cassette::load(&path);
+    loaded.expect("Failed to load cassette");
+}
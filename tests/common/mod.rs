@@ -0,0 +1,52 @@
+//! Helpers shared by the integration tests
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A path to `name` in a directory of its own, so that tests (and concurrent
+/// test runs) never share files
+///
+/// The directory, and everything written to it (such as lock files), is
+/// removed when the `TempPath` is dropped.
+pub struct TempPath {
+    dir: PathBuf,
+    path: PathBuf,
+}
+
+impl TempPath {
+    pub fn new(name: &str) -> Self {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "nso-test-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed),
+        ));
+        // Left over from an aborted run by a process with the same ID
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir(&dir).expect("Failed to create temporary directory");
+        Self {
+            path: dir.join(name),
+            dir,
+        }
+    }
+}
+
+impl Deref for TempPath {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl AsRef<Path> for TempPath {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
@@ -0,0 +1,201 @@
+#![cfg(feature = "mock")]
+use nso::coral::{
+    update_presence_permission,
+    CoralErrorCode,
+    FriendCode,
+    PresencePermission,
+    PresenceState,
+};
+use nso::mock::{fixtures, MockServer};
+use nso::{NsoError, NsoSession};
+use serde_json::json;
+
+async fn start() -> (MockServer, NsoSession) {
+    let server = MockServer::start()
+        .await
+        .expect("Failed to start mock server");
+    let session = NsoSession::new(fixtures::SESSION_TOKEN, server.client());
+    (server, session)
+}
+
+#[tokio::test]
+async fn friends_are_listed() {
+    let (_server, session) = start().await;
+    let friends = session.friends().await.expect("Failed to get friends");
+    assert_eq!(friends.len(), 1);
+    assert_eq!(friends[0].nsa_id, fixtures::FRIEND_NSA_ID);
+    assert_eq!(friends[0].presence.state, PresenceState::Playing);
+    assert_eq!(
+        friends[0]
+            .presence
+            .game
+            .as_ref()
+            .map(|game| game.name.as_str()),
+        Some("Splatoon 3"),
+    );
+}
+
+#[tokio::test]
+async fn empty_presence_game_is_none() {
+    let (server, session) = start().await;
+    server.set_friend_presence(json!({
+        "state": "OFFLINE",
+        "updatedAt": 1_700_000_100,
+        "logoutAt": 1_700_000_100,
+        "game": {},
+    }));
+    let friends = session.friends().await.expect("Failed to get friends");
+    assert_eq!(friends[0].presence.game, None);
+}
+
+#[tokio::test]
+async fn web_service_tokens_are_fetched() {
+    let (_server, session) = start().await;
+    let services = session
+        .web_services()
+        .await
+        .expect("Failed to list web services");
+    let service = services
+        .iter()
+        .find(|service| service.name == "Splatoon 3")
+        .expect("Splatoon 3 isn't a web service");
+    assert_eq!(service.custom_attribute("verifyMembership"), Some("true"));
+    let service_token = session
+        .web_service_token(service.id)
+        .await
+        .expect("Failed to get web service token");
+    assert_eq!(service_token.expose(), fixtures::WEB_TOKEN);
+}
+
+#[test]
+fn friend_codes_are_parsed() {
+    let friend_code: FriendCode =
+        fixtures::FRIEND_CODE.parse().expect("Invalid friend code");
+    assert_eq!(
+        friend_code,
+        "123456789012".parse().expect("Invalid friend code")
+    );
+    assert_eq!(friend_code.to_string(), fixtures::FRIEND_CODE);
+    assert!(matches!(
+        "SW-1234-5678".parse::<FriendCode>(),
+        Err(NsoError::InvalidFriendCode(_)),
+    ));
+}
+
+#[tokio::test]
+async fn current_user_has_friend_code() {
+    let (_server, session) = start().await;
+    let friend_code: FriendCode =
+        fixtures::FRIEND_CODE.parse().expect("Invalid friend code");
+    let current_user = session
+        .current_user()
+        .await
+        .expect("Failed to get current user");
+    assert_eq!(current_user.friend_code(), friend_code);
+    let friend_code_url = session
+        .friend_code_url()
+        .await
+        .expect("Failed to create friend code URL");
+    assert_eq!(friend_code_url.friend_code, friend_code);
+}
+
+#[tokio::test]
+async fn friend_requests_are_sent_by_friend_code() {
+    let (_server, session) = start().await;
+    let stranger = session
+        .user_by_friend_code(
+            &fixtures::STRANGER_FRIEND_CODE
+                .parse()
+                .expect("Invalid friend code"),
+        )
+        .await
+        .expect("Failed to find user by friend code");
+    assert_eq!(stranger.nsa_id, fixtures::STRANGER_NSA_ID);
+    session
+        .send_friend_request(&stranger.nsa_id)
+        .await
+        .expect("Failed to send friend request");
+}
+
+#[tokio::test]
+async fn presence_permission_is_updated() {
+    let (_server, session) = start().await;
+    let permissions = session
+        .permissions()
+        .await
+        .expect("Failed to get permissions");
+    assert_eq!(permissions.presence, PresencePermission::Friends);
+    session
+        .set_presence_permission(PresencePermission::FavouriteFriends)
+        .await
+        .expect("Failed to update presence permission");
+    let updated = session
+        .permissions()
+        .await
+        .expect("Failed to get permissions");
+    assert_eq!(updated.presence, PresencePermission::FavouriteFriends);
+    assert_ne!(updated.etag, permissions.etag);
+}
+
+#[tokio::test]
+async fn stale_etag_is_rejected() {
+    let (_server, session) = start().await;
+    let permissions = session
+        .permissions()
+        .await
+        .expect("Failed to get permissions");
+    session
+        .set_presence_permission(PresencePermission::FavouriteFriends)
+        .await
+        .expect("Failed to update presence permission");
+    let login_token = session.login_token().await.expect("No login token");
+    match update_presence_permission(
+        PresencePermission::Nobody,
+        &permissions.etag,
+        &login_token,
+        session.client(),
+    )
+    .await
+    {
+        Err(NsoError::Coral(err)) => {
+            assert_eq!(err.code, CoralErrorCode::BadRequest);
+        },
+        Err(err) => panic!("Unexpected error: {err}"),
+        Ok(()) => panic!("Stale etag was accepted"),
+    }
+}
+
+#[tokio::test]
+async fn friends_are_favourited() {
+    let (_server, session) = start().await;
+    session
+        .favourite_friend(fixtures::FRIEND_NSA_ID)
+        .await
+        .expect("Failed to favourite friend");
+    let friends = session.friends().await.expect("Failed to get friends");
+    assert!(friends[0].is_favourite);
+    session
+        .unfavourite_friend(fixtures::FRIEND_NSA_ID)
+        .await
+        .expect("Failed to unfavourite friend");
+    let friends = session.friends().await.expect("Failed to get friends");
+    assert!(!friends[0].is_favourite);
+}
+
+#[tokio::test]
+async fn friends_are_deleted() {
+    let (_server, session) = start().await;
+    session
+        .delete_friend(fixtures::FRIEND_NSA_ID)
+        .await
+        .expect("Failed to delete friend");
+    assert!(session
+        .friends()
+        .await
+        .expect("Failed to get friends")
+        .is_empty());
+    assert!(matches!(
+        session.delete_friend(fixtures::FRIEND_NSA_ID).await,
+        Err(NsoError::Coral(_)),
+    ));
+}
@@ -0,0 +1,98 @@
+#![cfg(feature = "mock")]
+mod common;
+
+use std::path::Path;
+
+use common::TempPath;
+use nso::mock::{fixtures, MockRoute, MockServer};
+use nso::{Credentials, NsoError, NsoSession, TokenCache};
+
+#[cfg(unix)]
+fn assert_private(path: &Path) {
+    use std::os::unix::fs::PermissionsExt;
+
+    let metadata = std::fs::metadata(path).expect("File not written");
+    assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+}
+
+#[cfg(not(unix))]
+fn assert_private(_path: &Path) {}
+
+#[tokio::test]
+async fn cached_tokens_are_reused() {
+    let server = MockServer::start()
+        .await
+        .expect("Failed to start mock server");
+    let session = NsoSession::new(fixtures::SESSION_TOKEN, server.client());
+    for _ in 0..2 {
+        session
+            .splatoon3_tokens()
+            .await
+            .expect("Failed to get Splatoon 3 tokens");
+    }
+    assert_eq!(server.hits(MockRoute::AccountLogin), 1);
+    assert_eq!(server.hits(MockRoute::BulletToken), 1);
+}
+
+#[tokio::test]
+async fn saved_credentials_skip_the_login_chain() {
+    let server = MockServer::start()
+        .await
+        .expect("Failed to start mock server");
+    let session = NsoSession::new(fixtures::SESSION_TOKEN, server.client());
+    session
+        .splatoon3_tokens()
+        .await
+        .expect("Failed to get Splatoon 3 tokens");
+    let credentials = session.credentials().await;
+    for file_name in ["credentials.json", "credentials.toml"] {
+        let path = TempPath::new(file_name);
+        credentials.save(&path).expect("Failed to save credentials");
+        assert_private(&path);
+        let loaded = Credentials::load(&path).expect("Failed to load credentials");
+        assert_eq!(loaded, credentials);
+        let restored = NsoSession::from_credentials(&loaded, server.client())
+            .expect("Failed to restore session");
+        let tokens = restored
+            .splatoon3_tokens()
+            .await
+            .expect("Failed to get Splatoon 3 tokens");
+        assert_eq!(tokens.bullet_token.expose(), fixtures::BULLET_TOKEN);
+    }
+    assert_eq!(server.hits(MockRoute::AccountLogin), 1);
+    assert_eq!(server.hits(MockRoute::BulletToken), 1);
+}
+
+#[tokio::test]
+async fn shared_cache_refreshes_once() {
+    let server = MockServer::start()
+        .await
+        .expect("Failed to start mock server");
+    let path = TempPath::new("cache.json");
+    let first = NsoSession::new(fixtures::SESSION_TOKEN, server.client())
+        .with_cache(TokenCache::new(&*path));
+    let second = NsoSession::new(fixtures::SESSION_TOKEN, server.client())
+        .with_cache(TokenCache::new(&*path));
+    let (first, second) =
+        tokio::join!(first.splatoon3_tokens(), second.splatoon3_tokens());
+    assert_eq!(
+        first.expect("Failed to get Splatoon 3 tokens").bullet_token,
+        second
+            .expect("Failed to get Splatoon 3 tokens")
+            .bullet_token,
+    );
+    assert_eq!(server.hits(MockRoute::AccountLogin), 1);
+    assert_eq!(server.hits(MockRoute::BulletToken), 1);
+    assert_private(&path);
+}
+
+#[test]
+fn unversioned_credentials_are_rejected() {
+    assert!(matches!(
+        Credentials::from_json(&format!(
+            r#"{{"session_token": "{}"}}"#,
+            fixtures::SESSION_TOKEN,
+        )),
+        Err(NsoError::UnsupportedCredentialsVersion(0)),
+    ));
+}
@@ -0,0 +1,91 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use nso::f_token::{FToken, FailoverFTokenProvider, HashMethod, RetryPolicy};
+use nso::{FTokenProvider, NsoClient, NsoError};
+use reqwest::StatusCode;
+
+/// Rate-limits the first request, asking for `retry_after`, then succeeds
+struct RateLimited {
+    retry_after: Duration,
+    calls: AtomicU32,
+}
+
+impl RateLimited {
+    fn new(retry_after: Duration) -> Self {
+        Self {
+            retry_after,
+            calls: AtomicU32::new(0),
+        }
+    }
+}
+
+#[async_trait]
+impl FTokenProvider for RateLimited {
+    fn name(&self) -> &str {
+        "rate-limited"
+    }
+
+    async fn get_f(
+        &self,
+        _hash_method: HashMethod,
+        _token: &str,
+        _client: &NsoClient,
+    ) -> Result<FToken, NsoError> {
+        if self.calls.fetch_add(1, Ordering::SeqCst) == 0 {
+            return Err(NsoError::FToken {
+                status: StatusCode::TOO_MANY_REQUESTS,
+                reason: "slow down".to_string(),
+                retry_after: Some(self.retry_after),
+            });
+        }
+        Ok(FToken {
+            f: "f".to_string(),
+            timestamp: 0,
+            request_id: "request".to_string(),
+        })
+    }
+}
+
+fn failover(retry_after: Duration, max_backoff: Duration) -> FailoverFTokenProvider {
+    FailoverFTokenProvider::new()
+        .with_provider(RateLimited::new(retry_after), Duration::from_secs(5))
+        .with_retry_policy(RetryPolicy {
+            max_retries: 1,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff,
+        })
+}
+
+#[tokio::test]
+async fn retry_after_is_respected() {
+    let provider = failover(Duration::from_millis(200), Duration::from_secs(5));
+    let start = Instant::now();
+    provider
+        .get_f(HashMethod::Nso, "token", &NsoClient::default())
+        .await
+        .expect("Retry failed");
+    assert!(start.elapsed() >= Duration::from_millis(200));
+}
+
+#[tokio::test]
+async fn retry_after_is_capped() {
+    let provider = failover(Duration::from_secs(60), Duration::from_millis(20));
+    let start = Instant::now();
+    provider
+        .get_f(HashMethod::Nso, "token", &NsoClient::default())
+        .await
+        .expect("Retry failed");
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[tokio::test]
+async fn empty_failover_is_an_error() {
+    assert!(matches!(
+        FailoverFTokenProvider::new()
+            .get_f(HashMethod::Nso, "token", &NsoClient::default())
+            .await,
+        Err(NsoError::NoFTokenProviders),
+    ));
+}
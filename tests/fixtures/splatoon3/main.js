@@ -0,0 +1,3 @@
+/*! For license information please see main.bd36a652.js.LICENSE.txt */
+(()=>{var e={8124:(e,t,n)=>{"use strict";n.d(t,{Z:()=>r});const r={params:{id:"f8ae00773cc412a50dd41a6d9a159ddd",metadata:{},name:"CoopHistoryQuery",operationKind:"query",text:""}}},2731:(e,t,n)=>{"use strict";n.d(t,{Z:()=>r});const r={params:{id:"730cd98e84f1030d3e9ac86b6f1aae13",metadata:{},name:"StageScheduleQuery",operationKind:"query",text:""}}},4022:(e,t,n)=>{"use strict";n.d(t,{Z:()=>r});const r={params:{id:"51fc56bbf006caf37728914aa8bc0e2c86a80cf195b4d4027d6822a3623098a8",metadata:{},name:"HomeQuery",operationKind:"query",text:""}}},9011:(e,t,n)=>{"use strict";n.d(t,{Z:()=>r});const r={params:{id:"b79b7a101a243912754f72437e2ad7e5",metadata:{},name:"SaleGearDetailOrderGesotownGearMutation",operationKind:"mutation",text:""}}}};
+const Ce="bd36a652b2f9e7d1c0a4e8f3b6d5c2a1e0f9d8c7",Pe=`${void 0!==Ce?Ce:"revision_info_not_set"}`,Le=`2.0.0-${Pe.substring(0,8)}`;})();
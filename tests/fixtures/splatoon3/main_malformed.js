@@ -0,0 +1,3 @@
+/*! For license information please see main.bd36a652.js.LICENSE.txt */
+(()=>{var e={2731:(e,t,n)=>{"use strict";n.d(t,{Z:()=>r});const r={params:{id:"730cd98e84f1030d3e9ac86b6f1aae1",metadata:{},name:"StageScheduleQuery",operationKind:"query",text:""}}},4022:(e,t,n)=>{"use strict";n.d(t,{Z:()=>r});const r={params:{id:"51fc56bbf006caf37728914aa8bc0e2c86a80cf195b4d4027d6822a3623098a8",metadata:{},name:"1HomeQuery",operationKind:"query",text:""}}}};
+const Ce="bd36a652b2f9e7d1c0a4e8f3b6d5c2a1e0f9d8c",Pe=`${void 0!==Ce?Ce:"revision_info_not_set"}`,Le=`2.0-${Pe.substring(0,8)}`;})();
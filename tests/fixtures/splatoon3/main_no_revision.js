@@ -0,0 +1,3 @@
+/*! For license information please see main.bd36a652.js.LICENSE.txt */
+(()=>{var e={2731:(e,t,n)=>{"use strict";n.d(t,{Z:()=>r});const r={params:{metadata:{},operationKind:"query",text:""}}}};
+const Ae=`${navigator.userAgent}`,Le=`2.0.0-${Pe.substring(0,8)}`;})();
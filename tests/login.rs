@@ -0,0 +1,117 @@
+#![cfg(feature = "mock")]
+use nso::coral::CoralErrorCode;
+use nso::f_token::{FToken, StaticFTokenProvider};
+use nso::mock::{fixtures, MockFailure, MockRoute, MockServer};
+use nso::{LoginRequest, NsoError, NsoSession};
+
+fn callback_url(state: &str) -> String {
+    format!(
+        concat!(
+            "npf71b963c1b7b6d119://auth#session_state=mock",
+            "&session_token_code=mock-code&state={}",
+        ),
+        state,
+    )
+}
+
+#[tokio::test]
+async fn login_url_uses_accounts_endpoint() {
+    let server = MockServer::start()
+        .await
+        .expect("Failed to start mock server");
+    let client = server.client();
+    let login_request = LoginRequest::new(&client);
+    assert!(login_request.url.starts_with(&client.endpoints().accounts));
+}
+
+#[tokio::test]
+async fn login_request_debug_hides_secrets() {
+    let server = MockServer::start()
+        .await
+        .expect("Failed to start mock server");
+    let login_request = LoginRequest::new(&server.client());
+    let debug = format!("{login_request:?}");
+    assert!(!debug.contains(&login_request.state));
+    assert!(!debug.contains(&login_request.verifier));
+}
+
+#[tokio::test]
+async fn login_request_is_redeemed() {
+    let server = MockServer::start()
+        .await
+        .expect("Failed to start mock server");
+    let client = server.client();
+    let login_request = LoginRequest::new(&client);
+    let session_token = login_request
+        .redeem(&callback_url(&login_request.state), &client)
+        .await
+        .expect("Failed to get session_token");
+    assert_eq!(session_token.expose(), fixtures::SESSION_TOKEN);
+    let session = NsoSession::new(session_token, client);
+    let claims = session
+        .session_token_claims()
+        .expect("session_token is not a JWT");
+    assert_eq!(claims.sub, fixtures::NA_ID);
+}
+
+#[tokio::test]
+async fn forged_login_is_rejected() {
+    let server = MockServer::start()
+        .await
+        .expect("Failed to start mock server");
+    let login_request = LoginRequest::new(&server.client());
+    assert!(matches!(
+        login_request.verify_callback(&callback_url("forged")),
+        Err(NsoError::StateMismatch),
+    ));
+}
+
+#[tokio::test]
+async fn cancelled_login_is_rejected() {
+    let server = MockServer::start()
+        .await
+        .expect("Failed to start mock server");
+    let login_request = LoginRequest::new(&server.client());
+    assert!(matches!(
+        login_request.verify_callback(&format!(
+            "npf71b963c1b7b6d119://auth#error=access_denied&state={}",
+            login_request.state,
+        )),
+        Err(NsoError::AuthorizationDenied { .. }),
+    ));
+}
+
+#[tokio::test]
+async fn coral_errors_are_typed() {
+    let server = MockServer::start()
+        .await
+        .expect("Failed to start mock server");
+    server.fail(MockRoute::AccountLogin, MockFailure::Coral(9427));
+    let session = NsoSession::new(fixtures::SESSION_TOKEN, server.client());
+    match session.login_token().await {
+        Err(NsoError::Coral(err)) => {
+            assert_eq!(err.code, CoralErrorCode::UpgradeRequired);
+        },
+        Err(err) => panic!("Unexpected error: {err}"),
+        Ok(_) => panic!("Injected failure was ignored"),
+    }
+}
+
+#[tokio::test]
+async fn static_f_tokens_are_used() {
+    let server = MockServer::start()
+        .await
+        .expect("Failed to start mock server");
+    let f_token = FToken {
+        f: fixtures::F.to_string(),
+        timestamp: 1_700_000_000,
+        request_id: "static-request-id".to_string(),
+    };
+    let session = NsoSession::new(fixtures::SESSION_TOKEN, server.client())
+        .with_f_token_provider(StaticFTokenProvider::new(f_token.clone(), f_token));
+    session
+        .web_service_token(nso::splatoon3::GAME_ID)
+        .await
+        .expect("Failed to get web service token");
+    assert_eq!(server.hits(MockRoute::FToken), 0);
+}
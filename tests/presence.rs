@@ -0,0 +1,54 @@
+#![cfg(feature = "mock")]
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use nso::mock::{fixtures, MockFailure, MockRoute, MockServer};
+use nso::presence::{watch_presence, PresenceEvent};
+use nso::NsoSession;
+use serde_json::json;
+
+#[tokio::test]
+async fn presence_changes_survive_a_rejected_login_token() {
+    let server = MockServer::start()
+        .await
+        .expect("Failed to start mock server");
+    server.set_friend_presence(json!({
+        "state": "ONLINE",
+        "updatedAt": 1_700_000_200,
+        "logoutAt": 1_700_000_100,
+        "game": {},
+    }));
+    let session = Arc::new(NsoSession::new(fixtures::SESSION_TOKEN, server.client()));
+    session
+        .login_token()
+        .await
+        .expect("Failed to get login token");
+    let watcher = watch_presence(session, Duration::from_millis(20));
+    futures_util::pin_mut!(watcher);
+    let change_presence = async {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        server.fail_times(MockRoute::FriendList, MockFailure::Coral(9404), 1);
+        server.set_friend_presence(json!({
+            "state": "PLAYING",
+            "updatedAt": 1_700_000_300,
+            "logoutAt": 1_700_000_100,
+            "game": {
+                "name": "Splatoon 3",
+                "imageUri": "https://example.com/splatoon3.jpg",
+                "shopUri": "https://example.com/splatoon3",
+                "totalPlayTime": 6060,
+                "firstPlayedAt": 1_662_000_000,
+                "sysDescription": "",
+            },
+        }));
+    };
+    let (event, ()) = tokio::join!(watcher.next(), change_presence);
+    match event {
+        Some(Ok(PresenceEvent::StartedPlaying { friend, game })) => {
+            assert_eq!(friend.name, fixtures::FRIEND_NAME);
+            assert_eq!(game.name, "Splatoon 3");
+        },
+        event => panic!("Unexpected presence event: {event:?}"),
+    }
+}
@@ -0,0 +1,208 @@
+#![cfg(feature = "mock")]
+mod common;
+
+use std::time::Duration;
+
+use common::TempPath;
+use nso::mock::{fixtures, MockFailure, MockRoute, MockServer};
+use nso::splatoon3::graphql_query;
+use nso::splatoon3::queries::QueryRegistry;
+use nso::splatoon3::version::{web_view_version, DEFAULT_WEB_VIEW_VERSION};
+use nso::{NsoClient, NsoError, NsoSession, Splatoon3Tokens};
+use reqwest::Response;
+
+async fn query(
+    server: &MockServer,
+    operation: &str,
+    client: &NsoClient,
+) -> Result<Response, NsoError> {
+    let Splatoon3Tokens {
+        web_token,
+        bullet_token,
+    } = NsoSession::new(fixtures::SESSION_TOKEN, server.client())
+        .splatoon3_tokens()
+        .await
+        .expect("Failed to get Splatoon 3 tokens");
+    graphql_query(
+        &bullet_token,
+        fixtures::LANGUAGE,
+        &web_token,
+        operation,
+        client,
+    )
+    .await
+}
+
+#[tokio::test]
+async fn splatoon3_tokens_are_fetched() {
+    let server = MockServer::start()
+        .await
+        .expect("Failed to start mock server");
+    let tokens = NsoSession::new(fixtures::SESSION_TOKEN, server.client())
+        .splatoon3_tokens()
+        .await
+        .expect("Failed to get Splatoon 3 tokens");
+    assert_eq!(tokens.web_token.expose(), fixtures::WEB_TOKEN);
+    assert_eq!(tokens.bullet_token.expose(), fixtures::BULLET_TOKEN);
+}
+
+#[tokio::test]
+async fn splatoon2_iksm_session_is_fetched() {
+    let server = MockServer::start()
+        .await
+        .expect("Failed to start mock server");
+    let iksm_session = NsoSession::new(fixtures::SESSION_TOKEN, server.client())
+        .splatoon2_iksm_session()
+        .await
+        .expect("Failed to get iksm_session");
+    assert_eq!(iksm_session.expose(), fixtures::IKSM_SESSION);
+}
+
+#[tokio::test]
+async fn graphql_response_keeps_its_url() {
+    let server = MockServer::start()
+        .await
+        .expect("Failed to start mock server");
+    let response = query(&server, "StageScheduleQuery", &server.client())
+        .await
+        .expect("GraphQL query failed");
+    assert_eq!(response.url().path(), "/api/graphql");
+    response.text().await.expect("GraphQL query failed");
+}
+
+#[tokio::test]
+async fn unbundled_queries_are_discovered() {
+    let server = MockServer::start()
+        .await
+        .expect("Failed to start mock server");
+    query(&server, fixtures::HOME_QUERY, &server.client())
+        .await
+        .expect("Discovered query failed");
+}
+
+#[tokio::test]
+async fn unknown_queries_are_rejected() {
+    let server = MockServer::start()
+        .await
+        .expect("Failed to start mock server");
+    assert!(matches!(
+        query(&server, "NoSuchQuery", &server.client()).await,
+        Err(NsoError::UnknownQuery(_)),
+    ));
+}
+
+#[tokio::test]
+async fn web_view_version_is_cached() {
+    let server = MockServer::start()
+        .await
+        .expect("Failed to start mock server");
+    let client = server.client();
+    assert_eq!(web_view_version(&client).await, fixtures::WEB_VIEW_VERSION);
+    assert_eq!(web_view_version(&client).await, fixtures::WEB_VIEW_VERSION);
+    assert_eq!(server.hits(MockRoute::MainScript), 1);
+}
+
+#[tokio::test]
+async fn web_view_version_can_be_pinned() {
+    let server = MockServer::start()
+        .await
+        .expect("Failed to start mock server");
+    let pinned = server.client().with_web_view_version("1.2.3-abcdef01");
+    assert_eq!(web_view_version(&pinned).await, "1.2.3-abcdef01");
+    assert_eq!(server.hits(MockRoute::MainScript), 0);
+}
+
+#[tokio::test]
+async fn web_view_version_is_retried_after_a_failure() {
+    let server = MockServer::start()
+        .await
+        .expect("Failed to start mock server");
+    server.fail_times(MockRoute::MainScript, MockFailure::Status(404), 1);
+    let client = server.client();
+    assert_eq!(web_view_version(&client).await, DEFAULT_WEB_VIEW_VERSION);
+    // The failure is cached until the retry delay has passed
+    assert_eq!(web_view_version(&client).await, DEFAULT_WEB_VIEW_VERSION);
+    assert_eq!(server.hits(MockRoute::MainScript), 1);
+    let retrying = server
+        .client()
+        .with_web_view_retry_delay(Duration::from_millis(10));
+    server.fail_times(MockRoute::MainScript, MockFailure::Status(404), 1);
+    assert_eq!(web_view_version(&retrying).await, DEFAULT_WEB_VIEW_VERSION);
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert_eq!(
+        web_view_version(&retrying).await,
+        fixtures::WEB_VIEW_VERSION
+    );
+}
+
+#[tokio::test]
+async fn stale_queries_are_reported() {
+    let server = MockServer::start()
+        .await
+        .expect("Failed to start mock server");
+    let client = server.client();
+    query(&server, fixtures::HOME_QUERY, &client)
+        .await
+        .expect("Discovered query failed");
+    server.rotate_query(fixtures::HOME_QUERY, "f".repeat(64));
+    match query(&server, fixtures::HOME_QUERY, &client).await {
+        Err(NsoError::StaleQuery { operation, .. }) => {
+            assert_eq!(operation, fixtures::HOME_QUERY);
+        },
+        Err(err) => panic!("Unexpected error: {err}"),
+        Ok(_) => panic!("Stale query was accepted"),
+    }
+}
+
+#[tokio::test]
+async fn stale_queries_are_refetched() {
+    let server = MockServer::start()
+        .await
+        .expect("Failed to start mock server");
+    let path = TempPath::new("queries.json");
+    std::fs::write(
+        &path,
+        format!(
+            r#"{{"{}": "{}"}}"#,
+            fixtures::HOME_QUERY,
+            fixtures::HOME_QUERY_HASH,
+        ),
+    )
+    .expect("Failed to write query registry");
+    let queries = QueryRegistry::load(&path).expect("Failed to load queries");
+    server.rotate_query(fixtures::HOME_QUERY, "f".repeat(64));
+    let refetching = server
+        .client()
+        .with_persisted_queries(queries)
+        .with_stale_query_refetch(true);
+    query(&server, fixtures::HOME_QUERY, &refetching)
+        .await
+        .expect("Stale query was not refetched");
+}
+
+#[tokio::test]
+async fn failed_refetch_keeps_what_was_discovered() {
+    let server = MockServer::start()
+        .await
+        .expect("Failed to start mock server");
+    let client = server.client().with_stale_query_refetch(true);
+    let tokens = NsoSession::new(fixtures::SESSION_TOKEN, client.clone())
+        .splatoon3_tokens()
+        .await
+        .expect("Failed to get Splatoon 3 tokens");
+    let query = || {
+        graphql_query(
+            &tokens.bullet_token,
+            fixtures::LANGUAGE,
+            &tokens.web_token,
+            fixtures::HOME_QUERY,
+            &client,
+        )
+    };
+    query().await.expect("Discovered query failed");
+    server.rotate_query(fixtures::HOME_QUERY, "f".repeat(64));
+    server.fail_times(MockRoute::MainScript, MockFailure::Status(404), 1);
+    assert!(matches!(query().await, Err(NsoError::StaleQuery { .. })));
+    assert_eq!(server.hits(MockRoute::MainScript), 2);
+    assert_eq!(web_view_version(&client).await, fixtures::WEB_VIEW_VERSION);
+}
@@ -0,0 +1,93 @@
+#![cfg(all(feature = "mock", feature = "vault"))]
+mod common;
+
+use std::path::Path;
+
+use common::TempPath;
+use nso::mock::{fixtures, MockServer};
+use nso::vault::{KdfParams, Vault};
+use nso::{Credentials, NsoError, NsoSession};
+use serde_json::json;
+
+const KDF: KdfParams = KdfParams {
+    memory_kib: 1024,
+    iterations: 1,
+    parallelism: 1,
+};
+
+async fn credentials() -> Credentials {
+    let server = MockServer::start()
+        .await
+        .expect("Failed to start mock server");
+    let session = NsoSession::new(fixtures::SESSION_TOKEN, server.client());
+    session
+        .splatoon3_tokens()
+        .await
+        .expect("Failed to get Splatoon 3 tokens");
+    session.credentials().await
+}
+
+fn create(path: &Path, credentials: Credentials) {
+    Vault::create(path, "correct horse", KDF, credentials)
+        .expect("Failed to create vault")
+        .lock()
+        .expect("Failed to lock vault");
+}
+
+#[tokio::test]
+async fn vault_opens_with_the_right_passphrase() {
+    let path = TempPath::new("vault.json");
+    let credentials = credentials().await;
+    create(&path, credentials.clone());
+    assert!(matches!(
+        Vault::open(&*path)
+            .expect("Failed to open vault")
+            .unlock("battery staple"),
+        Err(NsoError::IncorrectPassphrase),
+    ));
+    let vault = Vault::open(&*path)
+        .expect("Failed to open vault")
+        .unlock("correct horse")
+        .expect("Failed to unlock vault");
+    assert_eq!(*vault.credentials(), credentials);
+}
+
+#[tokio::test]
+async fn vault_with_unreasonable_kdf_is_invalid() {
+    let path = TempPath::new("crafted-vault.json");
+    create(&path, credentials().await);
+    let mut crafted: serde_json::Value = serde_json::from_str(
+        &std::fs::read_to_string(&path).expect("Failed to read vault"),
+    )
+    .expect("Vault is not JSON");
+    crafted["kdf"]["memory_kib"] = json!(u32::MAX);
+    std::fs::write(&path, crafted.to_string()).expect("Failed to write vault");
+    let opened = Vault::open(&*path);
+    assert!(matches!(opened, Err(NsoError::InvalidVault(_))));
+}
+
+#[tokio::test]
+async fn vault_passphrase_is_changed() {
+    let path = TempPath::new("rekeyed-vault.json");
+    let credentials = credentials().await;
+    create(&path, credentials.clone());
+    let mut vault = Vault::open(&*path)
+        .expect("Failed to open vault")
+        .unlock("correct horse")
+        .expect("Failed to unlock vault");
+    vault
+        .change_passphrase("battery staple")
+        .expect("Failed to change passphrase");
+    vault.save().expect("Failed to save vault");
+    assert!(matches!(
+        Vault::open(&*path)
+            .expect("Failed to open vault")
+            .unlock("correct horse"),
+        Err(NsoError::IncorrectPassphrase),
+    ));
+    let vault = Vault::open(&*path)
+        .expect("Failed to open vault")
+        .unlock("battery staple")
+        .expect("Failed to unlock vault");
+    assert_eq!(*vault.credentials(), credentials);
+}